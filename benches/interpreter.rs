@@ -0,0 +1,58 @@
+use chip8_emulator_rs::cpu::{CPUVariant, CPU};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+// Instructions run per iteration
+const STEPS: usize = 100_000;
+
+// Tight loop of arithmetic and BCD stores, drawing would dominate the time spent decoding
+const ROM: [u8; 22] = [
+    0x60, 0x00, // 200: v0 := 0
+    0x61, 0x00, // 202: v1 := 0
+    0xA3, 0x00, // 204: i := 0x300
+    0x70, 0x01, // 206: v0 += 1
+    0x81, 0x04, // 208: v1 += v0
+    0x82, 0x02, // 20A: v2 &= v0
+    0xF2, 0x33, // 20C: bcd v2
+    0x83, 0x13, // 20E: v3 ^= v1
+    0x30, 0x00, // 210: if v0 != 0 then
+    0x12, 0x06, // 212: jump 0x206
+    0x12, 0x00, // 214: jump 0x200
+];
+
+fn setup(cached: bool, jit: bool) -> CPU {
+    let mut cpu = CPU::new(CPUVariant::Chip8);
+    cpu.set_decode_cache(cached);
+    #[cfg(feature = "jit")]
+    if jit {
+        cpu.enable_jit().unwrap();
+    }
+    #[cfg(not(feature = "jit"))]
+    let _ = jit;
+    cpu.load_rom_bytes(&ROM);
+    cpu
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    let mut cases = vec![("uncached", false, false), ("cached", true, false)];
+    if cfg!(feature = "jit") {
+        cases.push(("jit", true, true));
+    }
+
+    for (name, cached, jit) in cases {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || setup(cached, jit),
+                |mut cpu| {
+                    cpu.run(STEPS);
+                    cpu
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,327 @@
+// Static analysis of ROMs that aren't in the database
+// Walks reachable code from the entry point, looking for platform specific opcodes
+// and for usage patterns that only behave correctly under certain quirks
+
+use std::collections::BTreeSet;
+
+use crate::cpu::{CPUVariant, Instruction, QuirkOverrides, LOAD_STORE_LOOKAHEAD};
+
+const PC_START: usize = 0x200;
+const MEMORY_SIZE: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FindingKind {
+    SChipOpcode,    // Opcode that only exists on SCHIP and later
+    XOChipOpcode,   // Opcode that only exists on XO-CHIP
+    ShiftQuirk,     // 8XY6/8XYE with X != Y, result differs under shift_quirk
+    JumpQuirk,      // BNNN with X != 0, target differs under jump_quirk
+    LoadStoreQuirk, // FX55/FX65 followed by code that uses I before it's reset
+    IndirectJump,   // BNNN, code past this point can't be followed statically
+    HiresEntry,     // "1260" at the entry point, used by two page CHIP-8 hires ROMs
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Finding {
+    pub address: usize,
+    pub instruction: Instruction,
+    pub kind: FindingKind,
+}
+
+pub struct Analysis {
+    pub variant: CPUVariant,
+    pub quirks: QuirkOverrides,
+    pub confidence: f32,            // 0.0 to 1.0
+    pub reachable: BTreeSet<usize>, // Addresses of reachable instructions
+    pub findings: Vec<Finding>,
+}
+
+impl Analysis {
+    pub fn has(&self, kind: FindingKind) -> bool {
+        self.findings.iter().any(|finding| finding.kind == kind)
+    }
+}
+
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let fetch = |address: usize| -> Option<u16> {
+        let offset = address.checked_sub(PC_START)?;
+        let upper = *rom.get(offset)?;
+        let lower = *rom.get(offset + 1)?;
+        Some(((upper as u16) << 8) | lower as u16)
+    };
+
+    let mut reachable = BTreeSet::new();
+    let mut findings = vec![];
+    let mut written_registers = 0u16;
+
+    // Flood fill from the entry point
+    let mut pending = vec![PC_START];
+    while let Some(address) = pending.pop() {
+        if address >= MEMORY_SIZE || !reachable.insert(address) {
+            continue;
+        }
+
+        let Some(opcode) = fetch(address) else {
+            continue;
+        };
+        let instruction = Instruction::decode(opcode);
+        let next = address + instruction.size();
+
+        if let Some(kind) = platform_finding(instruction) {
+            findings.push(Finding {
+                address,
+                instruction,
+                kind,
+            });
+        }
+
+        if let Some(x) = written_register(instruction) {
+            written_registers |= 1 << x;
+        }
+
+        match instruction {
+            Instruction::ShiftRight(x, y) | Instruction::ShiftLeft(x, y) if x != y => findings
+                .push(Finding {
+                    address,
+                    instruction,
+                    kind: FindingKind::ShiftQuirk,
+                }),
+            Instruction::Store(_) | Instruction::Load(_) if uses_i_after(&fetch, next) => findings
+                .push(Finding {
+                    address,
+                    instruction,
+                    kind: FindingKind::LoadStoreQuirk,
+                }),
+            _ => (),
+        }
+
+        if address == PC_START && instruction == Instruction::Jump(0x260) {
+            findings.push(Finding {
+                address,
+                instruction,
+                kind: FindingKind::HiresEntry,
+            });
+
+            // The interpreter redirects this jump to the start of the hires program
+            pending.push(0x2C0);
+        }
+
+        match instruction {
+            Instruction::Jump(nnn) => pending.push(nnn as usize),
+            Instruction::Call(nnn) => {
+                pending.push(nnn as usize);
+                pending.push(next);
+            }
+            Instruction::JumpOffset(nnn) => {
+                if nnn >> 8 != 0 {
+                    findings.push(Finding {
+                        address,
+                        instruction,
+                        kind: FindingKind::JumpQuirk,
+                    });
+                }
+                findings.push(Finding {
+                    address,
+                    instruction,
+                    kind: FindingKind::IndirectJump,
+                });
+            }
+            Instruction::Return | Instruction::Exit | Instruction::Unknown(_) => (),
+            _ if instruction.is_skip() => {
+                pending.push(next);
+
+                // Skips jump over F000 NNNN entirely on XO-CHIP
+                let skipped_size = fetch(next)
+                    .map(|opcode| Instruction::decode(opcode).size())
+                    .unwrap_or(2);
+                pending.push(next + skipped_size);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    recommend(reachable, findings, written_registers)
+}
+
+fn platform_finding(instruction: Instruction) -> Option<FindingKind> {
+    match instruction {
+        Instruction::ScrollDown(_)
+        | Instruction::ScrollRight
+        | Instruction::ScrollLeft
+        | Instruction::Exit
+        | Instruction::Lores
+        | Instruction::Hires
+        | Instruction::SetIBigSprite(_)
+        | Instruction::StoreFlags(_)
+        | Instruction::LoadFlags(_) => Some(FindingKind::SChipOpcode),
+        Instruction::ScrollUp(_)
+        | Instruction::SaveRange(..)
+        | Instruction::LoadRange(..)
+        | Instruction::SetILong
+        | Instruction::Plane(_)
+        | Instruction::Audio
+        | Instruction::Pitch(_) => Some(FindingKind::XOChipOpcode),
+        _ => None,
+    }
+}
+
+fn written_register(instruction: Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::SetImm(x, _)
+        | Instruction::AddImm(x, _)
+        | Instruction::Move(x, _)
+        | Instruction::Or(x, _)
+        | Instruction::And(x, _)
+        | Instruction::Xor(x, _)
+        | Instruction::Add(x, _)
+        | Instruction::Sub(x, _)
+        | Instruction::ShiftRight(x, _)
+        | Instruction::SubReverse(x, _)
+        | Instruction::ShiftLeft(x, _)
+        | Instruction::Random(x, _)
+        | Instruction::GetDelay(x)
+        | Instruction::WaitKey(x) => Some(x),
+        _ => None,
+    }
+}
+
+// Check whether code after a load/store relies on where I ends up
+fn uses_i_after(fetch: &impl Fn(usize) -> Option<u16>, mut address: usize) -> bool {
+    for _ in 0..LOAD_STORE_LOOKAHEAD {
+        let Some(opcode) = fetch(address) else {
+            return false;
+        };
+
+        match Instruction::decode(opcode) {
+            Instruction::Store(_)
+            | Instruction::Load(_)
+            | Instruction::Bcd(_)
+            | Instruction::Draw(..)
+            | Instruction::AddI(_) => return true,
+            Instruction::SetI(_)
+            | Instruction::SetILong
+            | Instruction::SetISprite(_)
+            | Instruction::SetIBigSprite(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpOffset(_)
+            | Instruction::Call(_)
+            | Instruction::Return => return false,
+            instruction => address += instruction.size(),
+        }
+    }
+
+    false
+}
+
+fn recommend(
+    reachable: BTreeSet<usize>,
+    findings: Vec<Finding>,
+    written_registers: u16,
+) -> Analysis {
+    let mut analysis = Analysis {
+        variant: CPUVariant::Chip8,
+        quirks: QuirkOverrides::default(),
+        confidence: 0.5, // No platform opcodes is weak evidence for plain CHIP-8
+        reachable,
+        findings,
+    };
+
+    // Two page hires ROMs always start by jumping over the mode switch
+    let hires_entry = analysis.has(FindingKind::HiresEntry);
+
+    if analysis.has(FindingKind::XOChipOpcode) {
+        analysis.variant = CPUVariant::XOChip;
+        analysis.confidence = 0.95;
+    } else if analysis.has(FindingKind::SChipOpcode) {
+        analysis.variant = CPUVariant::SChipv1_1;
+        analysis.confidence = 0.85;
+    } else if hires_entry {
+        analysis.variant = CPUVariant::Chip8Hires;
+        analysis.confidence = 0.9;
+    }
+
+    // BXNN only makes sense as "jump to XNN + VX" if the ROM sets VX but never V0
+    let jump_registers = analysis
+        .findings
+        .iter()
+        .filter_map(|finding| match finding.instruction {
+            Instruction::JumpOffset(nnn) if finding.kind == FindingKind::JumpQuirk => {
+                Some((nnn >> 8) as u8)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !jump_registers.is_empty() {
+        let v0_written = written_registers & 1 != 0;
+        let vx_written = jump_registers
+            .iter()
+            .all(|&x| written_registers & (1 << x) != 0);
+
+        match (v0_written, vx_written) {
+            (false, true) => analysis.quirks.jump_quirk = Some(true),
+            (true, false) => analysis.quirks.jump_quirk = Some(false),
+            _ => (),
+        }
+    }
+
+    // Likewise, shifting VY into VX is unlikely to be intended if VY is never set
+    let shift_registers = analysis
+        .findings
+        .iter()
+        .filter_map(|finding| match finding.instruction {
+            Instruction::ShiftRight(_, y) | Instruction::ShiftLeft(_, y) => Some(y),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !shift_registers.is_empty()
+        && shift_registers
+            .iter()
+            .all(|&y| written_registers & (1 << y) == 0)
+    {
+        analysis.quirks.shift_quirk = Some(true);
+    }
+
+    // Code that carries on using I after FX55/FX65 expects it to have moved past the
+    // registers, as on the VIP
+    if analysis.has(FindingKind::LoadStoreQuirk) {
+        analysis.quirks.load_store_offset = Some(Some(1));
+    }
+
+    // Quirk-dependent code the variant default might get wrong lowers confidence
+    for kind in [
+        FindingKind::ShiftQuirk,
+        FindingKind::LoadStoreQuirk,
+        FindingKind::JumpQuirk,
+    ] {
+        if analysis.has(kind) {
+            analysis.confidence -= 0.05;
+        }
+    }
+
+    // Indirect jumps hide code from the scan, so anything past them is unknown
+    if analysis.has(FindingKind::IndirectJump) {
+        analysis.confidence -= 0.1;
+    }
+
+    analysis.confidence = analysis.confidence.clamp(0.0, 1.0);
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recommends_load_store_offset() {
+        // Stores V0-V1 then reads on from where I ended up
+        let rom = [0x60, 0x01, 0xA3, 0x00, 0xF1, 0x55, 0xF0, 0x65, 0x12, 0x08];
+        let analysis = analyze(&rom);
+        assert!(analysis.has(FindingKind::LoadStoreQuirk));
+        assert_eq!(analysis.quirks.load_store_offset, Some(Some(1)));
+
+        // I is reset before it's used again
+        let rom = [0x60, 0x01, 0xA3, 0x00, 0xF1, 0x55, 0xA3, 0x00, 0x12, 0x08];
+        let analysis = analyze(&rom);
+        assert!(!analysis.has(FindingKind::LoadStoreQuirk));
+        assert_eq!(analysis.quirks.load_store_offset, None);
+    }
+}
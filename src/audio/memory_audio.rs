@@ -0,0 +1,42 @@
+use super::Audio;
+
+// Records when the beeper starts and stops instead of playing it, for tests and headless runs
+// Frontends call resume_audio or pause_audio once a frame, so each call counts as a frame
+#[derive(Default)]
+pub struct MemoryAudio {
+    frame: u64,
+    playing: bool,
+    transitions: Vec<(u64, bool)>, // Frame, and whether the beeper turned on or off
+}
+
+impl MemoryAudio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn transitions(&self) -> &[(u64, bool)] {
+        &self.transitions
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.transitions.push((self.frame, playing));
+            self.playing = playing;
+        }
+        self.frame += 1;
+    }
+}
+
+impl Audio for MemoryAudio {
+    fn resume_audio(&mut self) {
+        self.set_playing(true);
+    }
+
+    fn pause_audio(&mut self) {
+        self.set_playing(false);
+    }
+}
@@ -1,6 +1,41 @@
+pub mod memory_audio;
 pub mod sdl_audio;
+pub mod wav;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// The beeper's tone
+const PITCH: f32 = 440.0;
+const VOLUME: f32 = 0.25;
 
 pub trait Audio {
     fn resume_audio(&mut self);
     fn pause_audio(&mut self);
 }
+
+// Square wave at the beeper's pitch, shared by live output and recordings so they sound the same
+pub struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl SquareWave {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            phase_inc: PITCH / sample_rate as f32,
+            phase: 0.0,
+            volume: VOLUME,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let sample = if self.phase <= 0.5 {
+            self.volume
+        } else {
+            -self.volume
+        };
+        self.phase = (self.phase + self.phase_inc) % 1.0;
+        sample
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::{SquareWave, SAMPLE_RATE};
+use crate::FRAME_RATE;
+
+const HEADER_SIZE: u32 = 44;
+
+// 16-bit mono PCM recording of the beeper, one frame of samples at a time so it stays in step
+// with emulated time however fast the emulator actually runs
+pub struct WavWriter {
+    file: BufWriter<File>,
+    wave: SquareWave,
+    samples: u32,
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // Sizes are filled in by finish
+        write_header(&mut file, 0)?;
+
+        Ok(Self {
+            file,
+            wave: SquareWave::new(SAMPLE_RATE),
+            samples: 0,
+        })
+    }
+
+    // Call once per emulated frame, with whether the sound timer was running
+    // Each frame gets exactly SAMPLE_RATE / FRAME_RATE samples
+    pub fn add_frame(&mut self, beeping: bool) -> Result<(), Box<dyn std::error::Error>> {
+        for _ in 0..SAMPLE_RATE / FRAME_RATE {
+            // Like the live output, the wave only moves on while it's audible
+            let sample = match beeping {
+                true => (self.wave.next_sample() * i16::MAX as f32) as i16,
+                false => 0,
+            };
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += SAMPLE_RATE / FRAME_RATE;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.file, self.samples * 2)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, data_size: u32) -> std::io::Result<()> {
+    out.write_all(b"RIFF")?;
+    out.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?; // Format chunk size
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // Mono
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // Bytes per second
+    out.write_all(&2u16.to_le_bytes())?; // Bytes per sample
+    out.write_all(&16u16.to_le_bytes())?; // Bits per sample
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())
+}
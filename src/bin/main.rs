@@ -1,74 +1,572 @@
 use chip8_emulator_rs::{
-    audio::{sdl_audio::SDLAudio, Audio},
-    cpu::CPU,
-    input::{sdl_input::SDLInput, Input, InputEvent, InputKey},
-    video::{sdl_video::SDLVideo, Video},
-    SCREEN_WIDTH,
+    analysis,
+    audio::{memory_audio::MemoryAudio, sdl_audio::SDLAudio, wav::WavWriter, Audio},
+    coverage::Coverage,
+    cpu::{DebugAction, Font, FontSet, CPU},
+    database::{self, Database, RomInfo},
+    input::{
+        script_input::ScriptInput, sdl_input::SDLInput, terminal_input::TerminalInput, Input,
+        InputEvent, InputKey,
+    },
+    lint::Linter,
+    profiler::Profiler,
+    video::{
+        capture::{self, GifRecorder, Y4mWriter},
+        filter::{Filter, Frame, Pipeline},
+        memory_video::MemoryVideo,
+        palette::{Palette, PaletteStore},
+        sdl_video::SDLVideo,
+        terminal_video::{Glyphs, TerminalVideo},
+        Scaling, Video, VP590_COLORS,
+    },
+    FRAME_RATE, SCREEN_WIDTH,
 };
 
 const VIDEO_WIDTH: usize = SCREEN_WIDTH * 16;
+const DEFAULT_TICKRATE: usize = 20;
+const DEFAULT_DATABASE: &str = "programs.json";
+const PALETTE_STORE: &str = "palettes.json";
+const DEFAULT_CAPTURE_SCALE: usize = 4;
 
-use std::time::{Duration, Instant};
+// How long a terminal key stays held after the terminal last sent it. It has to outlast
+// the delay before keys start repeating (500 ms on GNOME and Windows, 660 ms on X11) or
+// held keys get released in between, at the cost of taps lasting that long too
+const DEFAULT_KEY_HOLD: Duration = Duration::from_millis(700);
+
+// Minimum confidence to prefer the analyzer's guess over the file extension
+const ANALYSIS_CONFIDENCE: f32 = 0.75;
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+struct Args {
+    rom: String,
+    database: Option<String>,
+    font: Option<String>,
+    font_location: Option<usize>,
+    profile: Option<String>, // Where to write folded stacks, the report goes to stdout
+    coverage: Option<String>, // Path prefix for the coverage .json and .png
+    debug_hooks: Vec<(usize, DebugAction)>,
+    lint: bool,
+    scaling: Scaling,
+    vsync: bool,
+    palette: Option<Palette>,
+    filters: Vec<Filter>,
+    capture_scale: usize,     // Size of screenshot, GIF and Y4M pixels
+    record: Option<String>,   // Path prefix for the .y4m and .wav of the whole run
+    terminal: Option<Glyphs>, // Draw in the terminal instead of a window
+    key_hold: Duration,
+    headless: bool, // No window, sound or keyboard, and no waiting between frames
+    script: Option<String>, // Input events to replay in headless runs
+    frames: Option<u64>, // Stop after this many frames
+    screenshot: Option<String>, // Where to save the screen on exit
+}
+
+impl Args {
+    // Usage: main [--db <programs.json>] [--font <name|file>] [--font-base <addr>]
+    //             [--profile <stacks.folded>] [--coverage <prefix>]
+    //             [--hook <addr>=<break|regs|print|assert>]... [--lint]
+    //             [--scaling <integer|fit>] [--no-vsync] [--palette <name|colors>]
+    //             [--filter <filter[:amount]>[,...]]... [--capture-scale <n>]
+    //             [--record <prefix>] [--terminal] [--braille] [--key-hold <ms>]
+    //             [--headless] [--script <file>] [--frames <n>] [--screenshot <file.png>] [rom]
+    fn parse() -> Self {
+        let mut rom = String::from("test_rom.ch8");
+        let mut database = None;
+        let mut font = None;
+        let mut font_location = None;
+        let mut profile = None;
+        let mut coverage = None;
+        let mut debug_hooks = vec![];
+        let mut lint = false;
+        let mut scaling = Scaling::Integer;
+        let mut vsync = true;
+        let mut palette = None;
+        let mut filters = vec![];
+        let mut capture_scale = DEFAULT_CAPTURE_SCALE;
+        let mut record = None;
+        let mut terminal = None;
+        let mut key_hold = DEFAULT_KEY_HOLD;
+        let mut headless = false;
+        let mut script = None;
+        let mut frames = None;
+        let mut screenshot = None;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--db" => database = args.next(),
+                "--font" => font = args.next(),
+                "--font-base" => font_location = args.next().and_then(|arg| parse_number(&arg)),
+                "--profile" => profile = args.next(),
+                "--coverage" => coverage = args.next(),
+                "--lint" => lint = true,
+                "--scaling" => {
+                    scaling = args
+                        .next()
+                        .and_then(|arg| Scaling::from_name(&arg))
+                        .unwrap_or(scaling)
+                }
+                "--no-vsync" => vsync = false,
+                "--filter" => filters.extend(
+                    args.next()
+                        .iter()
+                        .flat_map(|arg| arg.split(','))
+                        .filter_map(Filter::from_name),
+                ),
+                "--capture-scale" => {
+                    capture_scale = args
+                        .next()
+                        .and_then(|arg| parse_number(&arg))
+                        .filter(|&scale| scale > 0)
+                        .unwrap_or(capture_scale)
+                }
+                "--record" => record = args.next(),
+                "--terminal" => terminal = Some(Glyphs::HalfBlock),
+                "--braille" => terminal = Some(Glyphs::Braille),
+                "--headless" => headless = true,
+                "--script" => script = args.next(),
+                "--frames" => frames = args.next().and_then(|arg| arg.parse().ok()),
+                "--screenshot" => screenshot = args.next(),
+                "--key-hold" => {
+                    key_hold = args
+                        .next()
+                        .and_then(|arg| parse_number(&arg))
+                        .map(|ms| Duration::from_millis(ms as u64))
+                        .unwrap_or(key_hold)
+                }
+                "--palette" => palette = args.next().and_then(|arg| Palette::parse(&arg)),
+                "--hook" => debug_hooks.extend(args.next().and_then(|arg| parse_hook(&arg))),
+                _ => rom = arg,
+            }
+        }
+
+        // Fall back to a database in the working directory, if there is one
+        if database.is_none() && Path::new(DEFAULT_DATABASE).exists() {
+            database = Some(String::from(DEFAULT_DATABASE));
+        }
+
+        Self {
+            rom,
+            database,
+            font,
+            font_location,
+            profile,
+            coverage,
+            debug_hooks,
+            lint,
+            scaling,
+            vsync,
+            palette,
+            filters,
+            capture_scale,
+            record,
+            terminal,
+            key_hold,
+            headless,
+            script,
+            frames,
+            screenshot,
+        }
+    }
+}
+
+// Accepts decimal or 0x-prefixed hex
+fn parse_number(arg: &str) -> Option<usize> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+// e.g. "pong-1718000000000.png", next to where the emulator was started
+fn capture_path(rom: &str, extension: &str) -> String {
+    let name = Path::new(rom)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("{}-{}.{}", name, time, extension)
+}
+
+// The screen in its palette (or CHIP-8X colours), run through the filters
+fn render_frame(cpu: &CPU, palette: &Palette, filters: &mut Pipeline) -> Frame {
+    let (width, height) = cpu.max_res;
+    let colors = cpu.color_map();
+    filters.render(cpu.display().rows(), width, height, |x, y| match colors {
+        Some(colors) => (
+            VP590_COLORS[colors.background() as usize],
+            VP590_COLORS[colors.foreground(x, y) as usize],
+        ),
+        None => (palette.background(), palette.foreground()),
+    })
+}
+
+// Messages while running, printing would scribble over the terminal's screen so they go in
+// its title there, and the screen is drawn again in case anything else got in the way
+fn notify<V: Video>(video: &mut V, terminal: bool, message: &str) {
+    match terminal {
+        true => {
+            video.set_title(message);
+            video.refresh();
+        }
+        false => println!("{}", message),
+    }
+}
+
+fn warn<V: Video>(video: &mut V, terminal: bool, message: &str) {
+    match terminal {
+        true => notify(video, terminal, message),
+        false => eprintln!("{}", message),
+    }
+}
+
+// e.g. "0x0F0=break"
+fn parse_hook(arg: &str) -> Option<(usize, DebugAction)> {
+    let (address, action) = arg.split_once('=')?;
+    Some((parse_number(address)?, DebugAction::from_name(action)?))
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let sdl_context = sdl2::init()?;
+    let args = Args::parse();
+    let rom = fs::read(&args.rom)?;
 
-    // Init CPU
-    let mut cpu = CPU::new(chip8_emulator_rs::cpu::CPUVariant::SChipv1_1);
+    let rom_info = match &args.database {
+        Some(path) => match Database::load(path) {
+            Ok(database) => database.lookup(&rom),
+            Err(error) => {
+                eprintln!("Couldn't load {}, carrying on without it: {}", path, error);
+                None
+            }
+        },
+        None => None,
+    };
 
-    // Init audio/input drivers
-    let mut sdl_audio = SDLAudio::new(&sdl_context)?;
-    let mut sdl_input = SDLInput::new(&sdl_context)?;
+    // Init CPU, preferring the database entry, then static analysis, then the file extension
+    let mut cpu = match &rom_info {
+        Some(info) => {
+            let mut cpu = CPU::new(info.variant);
+            cpu.apply_quirks(&info.quirks);
+            cpu
+        }
+        None => {
+            let analysis = analysis::analyze(&rom);
+            println!(
+                "ROM not in database, analysis suggests {:?} ({:.0}% confidence)",
+                analysis.variant,
+                analysis.confidence * 100.0
+            );
 
-    // Init video driver, use CPU resolution as basis
-    let scale = (VIDEO_WIDTH / cpu.max_res.0) as u32;
-    let mut sdl_video = SDLVideo::new(&sdl_context, scale, cpu.max_res.0, cpu.max_res.1)?;
+            let extension_variant = database::variant_from_extension(&args.rom);
+            match extension_variant {
+                Some(variant) if analysis.confidence < ANALYSIS_CONFIDENCE => CPU::new(variant),
+                _ => {
+                    let mut cpu = CPU::new(analysis.variant);
+                    cpu.apply_quirks(&analysis.quirks);
+                    cpu
+                }
+            }
+        }
+    };
+
+    // Command line font takes priority over the database, either a built-in name or a file
+    let font = match &args.font {
+        Some(font) => match FontSet::from_name(font) {
+            Some(set) => Some(Font::from(set)),
+            None => Some(Font::load(font)?),
+        },
+        None => rom_info.as_ref().and_then(|info| info.font).map(Font::from),
+    };
+    if font.is_some() || args.font_location.is_some() {
+        let font = font.unwrap_or_else(|| Font::from(cpu.font_set()));
+        let location = args.font_location.unwrap_or(cpu.font_range().start);
+        cpu.load_font(&font, location)?;
+    }
 
-    let frame_ms = Duration::from_nanos(16_666_666);
+    // A window through SDL, the terminal for consoles without a display (e.g. over SSH), or
+    // nothing at all for build servers
+    if args.headless {
+        let script_input = match &args.script {
+            Some(path) => ScriptInput::parse(&fs::read_to_string(path)?)?,
+            None => ScriptInput::default(),
+        };
+        return run(
+            cpu,
+            args,
+            rom,
+            rom_info,
+            MemoryVideo::new(),
+            script_input,
+            Some(MemoryAudio::new()),
+        );
+    }
 
-    let args = std::env::args();
-    let rom = match args.len() {
-        2 => args.last().unwrap(),
-        _ => String::from("test_rom.ch8"),
+    match args.terminal {
+        Some(glyphs) => {
+            // Sound still goes through SDL if there's an audio device
+            let sdl_audio = sdl2::init()
+                .ok()
+                .and_then(|sdl_context| SDLAudio::new(&sdl_context).ok());
+            let terminal_video = TerminalVideo::new(glyphs)?;
+            let terminal_input = TerminalInput::new(args.key_hold)?;
+            run(
+                cpu,
+                args,
+                rom,
+                rom_info,
+                terminal_video,
+                terminal_input,
+                sdl_audio,
+            )
+        }
+        None => {
+            let sdl_context = sdl2::init()?;
+
+            // Init audio/input drivers, carrying on without sound if there's no audio device
+            let sdl_audio = match SDLAudio::new(&sdl_context) {
+                Ok(sdl_audio) => Some(sdl_audio),
+                Err(error) => {
+                    eprintln!("No audio: {}", error);
+                    None
+                }
+            };
+            let sdl_input = SDLInput::new(&sdl_context)?;
+
+            // Init video driver, use CPU resolution as basis
+            let scale = (VIDEO_WIDTH / cpu.max_res.0) as u32;
+            let sdl_video = SDLVideo::new(
+                &sdl_context,
+                scale,
+                cpu.max_res.0,
+                cpu.max_res.1,
+                args.scaling,
+                args.vsync,
+            )?;
+
+            run(cpu, args, rom, rom_info, sdl_video, sdl_input, sdl_audio)
+        }
+    }
+}
+
+// Runs the ROM until it quits or stops, then prints and writes any reports
+fn run<V: Video, I: Input, A: Audio>(
+    mut cpu: CPU,
+    args: Args,
+    rom: Vec<u8>,
+    rom_info: Option<RomInfo>,
+    mut video: V,
+    mut input: I,
+    mut audio: Option<A>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let terminal = args.terminal.is_some();
+    let mut tickrate = DEFAULT_TICKRATE;
+    if let Some(info) = &rom_info {
+        tickrate = info.tickrate.unwrap_or(DEFAULT_TICKRATE);
+
+        video.set_title(&info.display_title());
+        for &(control, key) in &info.keys {
+            input.bind_control(control, key);
+        }
+    }
+
+    // Palette from the command line, then the one last picked for this ROM, then the database's
+    let mut palette_store = PaletteStore::load(PALETTE_STORE).unwrap_or_else(|error| {
+        eprintln!(
+            "Couldn't load {}, ignoring saved palettes: {}",
+            PALETTE_STORE, error
+        );
+        PaletteStore::new(PALETTE_STORE)
+    });
+    let mut palette = args
+        .palette
+        .or_else(|| palette_store.get(&rom))
+        .or_else(|| rom_info.as_ref().and_then(|info| info.colors))
+        .unwrap_or_default();
+    video.set_palette(&palette);
+
+    let mut filters = Pipeline::new(args.filters);
+    let mut recorder: Option<GifRecorder> = None;
+
+    // Recorded next to the live output, from the first frame to the last
+    let mut av_recording = match &args.record {
+        Some(prefix) => {
+            let (width, height) = cpu.max_res;
+            Some((
+                Y4mWriter::create(format!("{}.y4m", prefix), width, height, args.capture_scale)?,
+                WavWriter::create(format!("{}.wav", prefix))?,
+            ))
+        }
+        None => None,
     };
 
-    cpu.load_rom(rom);
+    let frame_ms = Duration::from_nanos(1_000_000_000 / FRAME_RATE as u64);
+
+    cpu.load_rom_bytes(&rom);
+
+    #[cfg(feature = "jit")]
+    cpu.enable_jit()?;
 
-    while cpu.running {
+    for &(address, action) in &args.debug_hooks {
+        cpu.set_debug_hook(address, action);
+    }
+
+    let linter = args.lint.then(|| {
+        let linter = Rc::new(RefCell::new(Linter::new(&cpu, rom.len())));
+        cpu.add_observer(Box::new(linter.clone()));
+        linter
+    });
+
+    let coverage = args.coverage.as_ref().map(|_| {
+        let coverage = Rc::new(RefCell::new(Coverage::new(cpu.pc(), rom.len())));
+        cpu.add_observer(Box::new(coverage.clone()));
+        coverage
+    });
+
+    let profiler = args.profile.as_ref().map(|_| {
+        let profiler = Rc::new(RefCell::new(Profiler::new(tickrate)));
+        cpu.add_observer(Box::new(profiler.clone()));
+        profiler
+    });
+
+    let mut frames = 0;
+    while cpu.running && args.frames.is_none_or(|limit| frames < limit) {
+        frames += 1;
         let frame_start_time = Instant::now();
-        if let Some(input) = sdl_input.poll_input() {
+        if let Some(input) = input.poll_input() {
             match input {
                 InputEvent::KeyPressed(key) => match key {
                     // Break if we quit
                     InputKey::Quit => break,
+                    InputKey::Pause if cpu.is_paused() => cpu.resume(),
+                    InputKey::Pause => cpu.pause(),
+                    InputKey::Screenshot => {
+                        let path = capture_path(&args.rom, "png");
+                        let frame = render_frame(&cpu, &palette, &mut Pipeline::default());
+                        match capture::save_png(&frame, args.capture_scale, &path) {
+                            Ok(()) => notify(&mut video, terminal, &format!("Saved {}", path)),
+                            Err(error) => warn(
+                                &mut video,
+                                terminal,
+                                &format!("Couldn't save screenshot: {}", error),
+                            ),
+                        }
+                    }
+                    InputKey::Record => match recorder.take() {
+                        Some(recorder) => match recorder.finish() {
+                            Ok(()) => notify(&mut video, terminal, "Recording saved"),
+                            Err(error) => warn(
+                                &mut video,
+                                terminal,
+                                &format!("Couldn't save recording: {}", error),
+                            ),
+                        },
+                        None => {
+                            let path = capture_path(&args.rom, "gif");
+                            let (width, height) = cpu.max_res;
+                            match GifRecorder::create(&path, width, height, args.capture_scale) {
+                                Ok(created) => {
+                                    let message = format!("Recording to {}", path);
+                                    notify(&mut video, terminal, &message);
+                                    recorder = Some(created);
+                                }
+                                Err(error) => warn(
+                                    &mut video,
+                                    terminal,
+                                    &format!("Couldn't start recording: {}", error),
+                                ),
+                            }
+                        }
+                    },
+                    InputKey::Palette => {
+                        palette = palette.next();
+                        video.set_palette(&palette);
+                        cpu.request_redraw();
+
+                        palette_store.set(&rom, palette);
+                        if let Err(error) = palette_store.save() {
+                            let message = format!("Couldn't save palette: {}", error);
+                            warn(&mut video, terminal, &message);
+                        }
+                    }
                     _ => cpu.press_key(key as u8),
                 },
                 InputEvent::KeyReleased(key) => match key {
-                    InputKey::Quit => (),
+                    InputKey::Quit
+                    | InputKey::Pause
+                    | InputKey::Palette
+                    | InputKey::Screenshot
+                    | InputKey::Record => (),
                     _ => cpu.release_key(key as u8),
                 },
+                InputEvent::WindowChanged => video.refresh(),
             }
         }
 
-        // Process CPU instructions
-        for _ in 0..20 {
-            cpu.process();
+        // Process CPU instructions, time stands still while paused
+        if !cpu.is_paused() {
+            cpu.run(tickrate);
 
-            if cpu.should_vblank() {
-                break;
+            cpu.decrement_timers();
+
+            if let Some(breakpoint) = cpu.take_breakpoint() {
+                notify(&mut video, terminal, &breakpoint.to_string());
             }
-        }
 
-        cpu.decrement_timers();
+            // Recordings follow emulated time, nothing is added while paused
+            let frame = (recorder.is_some() || av_recording.is_some())
+                .then(|| render_frame(&cpu, &palette, &mut Pipeline::default()));
+            if let (Some(active), Some(frame)) = (&mut recorder, &frame) {
+                if let Err(error) = active.add_frame(frame) {
+                    let message = format!("Recording stopped: {}", error);
+                    warn(&mut video, terminal, &message);
+                    recorder = None;
+                }
+            }
+            if let (Some((y4m, wav)), Some(frame)) = (&mut av_recording, &frame) {
+                let result = y4m
+                    .add_frame(frame)
+                    .and_then(|_| wav.add_frame(cpu.is_sound_active()));
+                if let Err(error) = result {
+                    let message = format!("Recording stopped: {}", error);
+                    warn(&mut video, terminal, &message);
+                    av_recording = None;
+                }
+            }
+        }
 
-        if cpu.is_sound_active() {
-            sdl_audio.resume_audio();
-        } else {
-            sdl_audio.pause_audio();
+        if let Some(audio) = &mut audio {
+            if cpu.is_sound_active() && !cpu.is_paused() {
+                audio.resume_audio();
+            } else {
+                audio.pause_audio();
+            }
         }
 
         cpu.reset_vblank();
-        sdl_video.draw_to_window(&cpu.pixels, cpu.max_res.0, cpu.max_res.1);
+
+        // Only redraw frames where something changed, or pixels are still fading
+        if cpu.display().is_dirty() || filters.is_animated() {
+            let (width, height) = cpu.max_res;
+            if !filters.is_empty() {
+                video.draw_frame(&render_frame(&cpu, &palette, &mut filters));
+            } else {
+                match cpu.color_map() {
+                    Some(colors) => {
+                        video.draw_colored_to_window(cpu.display().rows(), colors, width, height)
+                    }
+                    None => video.draw_to_window(cpu.display().rows(), width, height),
+                }
+            }
+            cpu.mark_displayed();
+        }
 
         // println!("Process + draw time: {}ms", a.elapsed().as_millis());
 
@@ -85,14 +583,71 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
          *
          */
 
+        // Headless runs go as fast as they can, nobody is watching
         let remaining_time = frame_ms.saturating_sub(frame_start_time.elapsed());
 
-        if !remaining_time.is_zero() {
+        if !remaining_time.is_zero() && !args.headless {
             std::thread::sleep(remaining_time);
         }
 
         // println!("Elapsed time for frame: {}ms", global_timer.elapsed().as_millis());
     }
 
+    // Give the terminal back before printing anything
+    drop(video);
+    drop(input);
+
+    if let Some(path) = &args.screenshot {
+        let frame = render_frame(&cpu, &palette, &mut Pipeline::default());
+        capture::save_png(&frame, args.capture_scale, path)?;
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+
+    if let Some((video, audio)) = av_recording {
+        video.finish()?;
+        audio.finish()?;
+    }
+
+    if let Some(fault) = cpu.fault() {
+        eprintln!("CPU stopped: {}", fault);
+    }
+
+    if let (Some(profiler), Some(path)) = (profiler, &args.profile) {
+        let profiler = profiler.borrow();
+        print!("{}", profiler.report());
+        fs::write(path, profiler.folded())?;
+    }
+
+    if let Some(linter) = linter {
+        let linter = linter.borrow();
+        match linter.findings().is_empty() {
+            true => println!("Lint: no problems found"),
+            false => print!("Lint:\n{}", linter.report()),
+        }
+    }
+
+    if let (Some(coverage), Some(prefix)) = (coverage, &args.coverage) {
+        let coverage = coverage.borrow();
+        let summary = coverage.summary();
+        println!(
+            "Coverage: {:.1}% of {} ROM bytes ({} code, {} data, {} written)",
+            summary.covered() * 100.0,
+            summary.total,
+            summary.code,
+            summary.data,
+            summary.written
+        );
+        fs::write(format!("{}.json", prefix), coverage.to_json()?)?;
+        coverage.write_png(format!("{}.png", prefix))?;
+    }
+
+    // Fail the run, so test scripts notice
+    if let Some(failure) = cpu.assert_failure() {
+        return Err(failure.to_string().into());
+    }
+
     Ok(())
 }
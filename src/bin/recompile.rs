@@ -0,0 +1,70 @@
+use chip8_emulator_rs::{
+    analysis,
+    cpu::CPU,
+    database::{self, Database},
+};
+
+use std::{fs, path::Path};
+
+const DEFAULT_DATABASE: &str = "programs.json";
+
+// Minimum confidence to prefer the analyzer's guess over the file extension
+const ANALYSIS_CONFIDENCE: f32 = 0.75;
+
+// Usage: recompile [--db <programs.json>] <rom> [output.rs]
+// Writes a Rust module for the ROM, to stdout if no output is given
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut database = None;
+    let mut paths = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--db" => database = args.next(),
+            _ => paths.push(arg),
+        }
+    }
+    if database.is_none() && Path::new(DEFAULT_DATABASE).exists() {
+        database = Some(String::from(DEFAULT_DATABASE));
+    }
+
+    let Some(rom_path) = paths.first() else {
+        return Err("usage: recompile [--db <programs.json>] <rom> [output.rs]".into());
+    };
+    let rom = fs::read(rom_path)?;
+
+    let rom_info = match &database {
+        Some(path) => match Database::load(path) {
+            Ok(database) => database.lookup(&rom),
+            Err(error) => {
+                eprintln!("Couldn't load {}, carrying on without it: {}", path, error);
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Same order as the emulator: database entry, then static analysis, then the file extension
+    let (variant, quirks) = match rom_info {
+        Some(info) => (info.variant, info.quirks),
+        None => {
+            let analysis = analysis::analyze(&rom);
+            match database::variant_from_extension(rom_path) {
+                Some(variant) if analysis.confidence < ANALYSIS_CONFIDENCE => {
+                    (variant, Default::default())
+                }
+                _ => (analysis.variant, analysis.quirks),
+            }
+        }
+    };
+
+    let mut cpu = CPU::new(variant);
+    cpu.apply_quirks(&quirks);
+
+    let source = cpu.recompile(variant, &rom);
+    match paths.get(1) {
+        Some(output) => fs::write(output, source)?,
+        None => print!("{}", source),
+    }
+    Ok(())
+}
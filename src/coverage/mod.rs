@@ -0,0 +1,235 @@
+// Which bytes of memory were executed, read as data or written while a ROM ran
+// Built on the CPU's observer hooks, exported as JSON or as a memory map image
+
+use std::{collections::BTreeSet, fs::File, io::BufWriter, ops::Range, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    cpu::{Instruction, Observer},
+    video::Rgb,
+};
+
+// Per byte flags
+const INSTRUCTION: u8 = 1 << 0; // Start of an executed instruction
+const CODE: u8 = 1 << 1;
+const DATA: u8 = 1 << 2; // Read by DXYN, FX65 and friends
+const WRITTEN: u8 = 1 << 3;
+
+// Memory map layout, one pixel per byte
+const MAP_WIDTH: usize = 64;
+const MAP_SCALE: usize = 4;
+
+const UNTOUCHED_COLOR: Rgb = Rgb(0x20, 0x20, 0x20);
+const CODE_COLOR: Rgb = Rgb(0x40, 0xC0, 0x40);
+const DATA_COLOR: Rgb = Rgb(0x40, 0x80, 0xE0);
+const WRITTEN_COLOR: Rgb = Rgb(0xE0, 0x40, 0x40);
+const CODE_WRITTEN_COLOR: Rgb = Rgb(0xF0, 0xD0, 0x40); // Self modifying code
+const DATA_WRITTEN_COLOR: Rgb = Rgb(0xC0, 0x60, 0xE0);
+const OUTSIDE_ROM_COLOR: Rgb = Rgb::BLACK;
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub code: usize,
+    pub data: usize,
+    pub written: usize,
+    pub untouched: usize,
+}
+
+impl Summary {
+    // Fraction of the ROM that was executed or read
+    pub fn covered(&self) -> f32 {
+        (self.total - self.untouched) as f32 / self.total.max(1) as f32
+    }
+}
+
+// A run of bytes with the same flags
+#[derive(Serialize)]
+struct Region {
+    start: usize,
+    end: usize, // Exclusive
+    code: bool,
+    data: bool,
+    written: bool,
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    rom_start: usize,
+    rom_end: usize,
+    summary: Summary,
+    regions: &'a [Region],
+}
+
+pub struct Coverage {
+    rom: Range<usize>,
+    bytes: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new(rom_start: usize, rom_len: usize) -> Self {
+        Self {
+            rom: rom_start..rom_start + rom_len,
+            bytes: vec![0; rom_start + rom_len],
+        }
+    }
+
+    // Totals over the ROM image, accesses elsewhere in memory don't count
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for address in self.rom.clone() {
+            let flags = self.flags(address);
+            summary.total += 1;
+            summary.code += (flags & CODE != 0) as usize;
+            summary.data += (flags & DATA != 0) as usize;
+            summary.written += (flags & WRITTEN != 0) as usize;
+            summary.untouched += (flags == 0) as usize;
+        }
+        summary
+    }
+
+    // Addresses where an executed instruction starts, like Analysis::reachable
+    pub fn instructions(&self) -> BTreeSet<usize> {
+        (0..self.bytes.len())
+            .filter(|&address| self.flags(address) & INSTRUCTION != 0)
+            .collect()
+    }
+
+    pub fn is_code(&self, address: usize) -> bool {
+        self.flags(address) & CODE != 0
+    }
+
+    pub fn is_data(&self, address: usize) -> bool {
+        self.flags(address) & DATA != 0
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let export = Export {
+            rom_start: self.rom.start,
+            rom_end: self.rom.end,
+            summary: self.summary(),
+            regions: &self.regions(),
+        };
+        Ok(serde_json::to_string_pretty(&export)?)
+    }
+
+    // Covers the ROM and anything touched past it, MAP_WIDTH bytes per row
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let rows = self.bytes.len().div_ceil(MAP_WIDTH);
+        let (width, height) = (MAP_WIDTH * MAP_SCALE, rows * MAP_SCALE);
+
+        let mut image = Vec::with_capacity(width * height * 3);
+        for row in 0..rows {
+            let colors: Vec<Rgb> = (row * MAP_WIDTH..(row + 1) * MAP_WIDTH)
+                .map(|address| self.color(address))
+                .collect();
+
+            for _ in 0..MAP_SCALE {
+                for &Rgb(r, g, b) in &colors {
+                    for _ in 0..MAP_SCALE {
+                        image.extend_from_slice(&[r, g, b]);
+                    }
+                }
+            }
+        }
+
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            width as u32,
+            height as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&image)?;
+        Ok(())
+    }
+
+    fn flags(&self, address: usize) -> u8 {
+        self.bytes.get(address).copied().unwrap_or(0)
+    }
+
+    fn mark(&mut self, address: usize, flags: u8) {
+        if address >= self.bytes.len() {
+            self.bytes.resize(address + 1, 0);
+        }
+        self.bytes[address] |= flags;
+    }
+
+    fn color(&self, address: usize) -> Rgb {
+        let flags = self.flags(address);
+        match (flags & CODE != 0, flags & DATA != 0, flags & WRITTEN != 0) {
+            (true, _, true) => CODE_WRITTEN_COLOR,
+            (true, _, false) => CODE_COLOR,
+            (false, true, true) => DATA_WRITTEN_COLOR,
+            (false, true, false) => DATA_COLOR,
+            (false, false, true) => WRITTEN_COLOR,
+            _ if self.rom.contains(&address) => UNTOUCHED_COLOR,
+            _ => OUTSIDE_ROM_COLOR,
+        }
+    }
+
+    // Untouched bytes outside the ROM are left out, there's nothing to say about them
+    fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = vec![];
+        for address in 0..self.bytes.len() {
+            let flags = self.flags(address) & !INSTRUCTION;
+            if flags == 0 && !self.rom.contains(&address) {
+                continue;
+            }
+
+            let (code, data, written) =
+                (flags & CODE != 0, flags & DATA != 0, flags & WRITTEN != 0);
+            match regions.last_mut() {
+                Some(region)
+                    if region.end == address
+                        && (region.code, region.data, region.written) == (code, data, written) =>
+                {
+                    region.end += 1
+                }
+                _ => regions.push(Region {
+                    start: address,
+                    end: address + 1,
+                    code,
+                    data,
+                    written,
+                }),
+            }
+        }
+        regions
+    }
+}
+
+impl Observer for Coverage {
+    fn on_fetch(&mut self, pc: usize, opcode: u16) {
+        self.mark(pc, INSTRUCTION | CODE);
+        // F000 NNNN carries its address in the next two bytes
+        for address in pc + 1..pc + Instruction::decode(opcode).size() {
+            self.mark(address, CODE);
+        }
+    }
+
+    fn on_memory_read(&mut self, address: usize, _value: u8, _opcode: u16) {
+        self.mark(address, DATA);
+    }
+
+    fn on_memory_write(&mut self, address: usize, _value: u8, _opcode: u16) {
+        self.mark(address, WRITTEN);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_instructions_are_code() {
+        let mut coverage = Coverage::new(0x200, 8);
+        coverage.on_fetch(0x200, 0xF000);
+        coverage.on_fetch(0x204, 0x00E0);
+
+        assert!((0x200..0x206).all(|address| coverage.is_code(address)));
+        assert!(!coverage.is_code(0x206));
+        assert_eq!(coverage.instructions(), BTreeSet::from([0x200, 0x204]));
+    }
+}
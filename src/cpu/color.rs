@@ -0,0 +1,55 @@
+use crate::MAX_RESOLUTION_HEIGHT;
+
+// CHIP-8X colour zones are 8 pixels wide, BXYN sets them one row at a time
+pub const ZONE_WIDTH: usize = 8;
+pub const ZONE_HEIGHT: usize = 4; // Height of a BXY0 zone
+pub const ZONE_COLUMNS: usize = 8;
+
+// VP-590 colour indices
+pub const COLOR_BLACK: u8 = 0;
+pub const COLOR_RED: u8 = 1;
+pub const COLOR_BLUE: u8 = 2;
+pub const COLOR_GREEN: u8 = 4;
+
+// 02A0 cycles through these
+const BACKGROUND_CYCLE: [u8; 4] = [COLOR_BLUE, COLOR_BLACK, COLOR_GREEN, COLOR_RED];
+
+// Colour attributes that sit alongside the pixel buffer (CHIP-8X)
+// Colours are VP-590 indices: black, red, blue, violet, green, yellow, aqua, white
+#[derive(Clone, Debug)]
+pub struct ColorMap {
+    pub zones: [[u8; ZONE_COLUMNS]; MAX_RESOLUTION_HEIGHT], // Foreground per zone column, per row
+    background_index: usize,
+}
+
+impl ColorMap {
+    pub fn background(&self) -> u8 {
+        BACKGROUND_CYCLE[self.background_index]
+    }
+
+    pub fn foreground(&self, x: usize, y: usize) -> u8 {
+        self.zones[y][(x / ZONE_WIDTH) % ZONE_COLUMNS]
+    }
+
+    pub(super) fn cycle_background(&mut self) {
+        self.background_index = (self.background_index + 1) % BACKGROUND_CYCLE.len();
+    }
+
+    pub(super) fn fill(&mut self, columns: (usize, usize), rows: (usize, usize), color: u8) {
+        for row in self.zones.iter_mut().skip(rows.0).take(rows.1) {
+            for zone in row.iter_mut().skip(columns.0).take(columns.1) {
+                *zone = color & 0x7;
+            }
+        }
+    }
+}
+
+// Red on blue at power on
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self {
+            zones: [[COLOR_RED; ZONE_COLUMNS]; MAX_RESOLUTION_HEIGHT],
+            background_index: 0,
+        }
+    }
+}
@@ -1,26 +1,57 @@
+use super::{
+    font::FontSet,
+    memory::{OutOfBounds, MEMORY_SIZE_CHIP8, MEMORY_SIZE_XO_CHIP},
+};
+
+// How many instructions after FX55/FX65 to look at for code that uses I, whose value there
+// depends on load_store_offset
+pub const LOAD_STORE_LOOKAHEAD: usize = 4;
+
 pub(super) struct CPUConfig {
     // Enabled features
     pub hires_enabled: bool,
     pub scrolling_enabled: bool,
     pub flag_registers_enabled: bool,
+    pub chip8e_enabled: bool,  // CHIP-8E skip, branch and timer opcodes
+    pub io_port_enabled: bool, // FX03/FXE3/FXE7 port I/O (CHIP-8E, CHIP-8I)
+    pub color_enabled: bool,   // CHIP-8X colour zones, second keypad and port I/O
 
     // Quirks
     pub logic_quirk: bool,  // Should set VF = 0 after AND/OR/XOR operation
     pub shift_quirk: bool,  // Should set VX to the shifted value of VX, not VY
     pub jump_quirk: bool,   // Should jump to XNN + VX, instead of NNN + V0
     pub vblank_quirk: bool, // Should set vblank interrupt (no processing until drawing finishes)
-    pub scroll_quirk: bool, // Should scroll by lores pixel size (e.g. 2x2)
+    pub scroll_quirk: bool, // Should scroll by lores pixel size (e.g. 2x2), otherwise by half a lores pixel
+    pub wrap_quirk: bool,   // Should wrap sprites around the screen edges, instead of clipping
+    pub row_collision_quirk: bool, // Should set VF to the number of collided/clipped rows in hires
+    pub hires_clear_quirk: bool, // Should clear the screen when switching between lores and hires
+    pub i_overflow_quirk: bool, // Should set VF = 1 when FX1E takes I past 0xFFF
+    pub key_wait_on_press: bool, // Should finish FX0A when a key is pressed, not released
+    pub flag_first_quirk: bool, // Should write VF before VX in 8XY4-8XYE, so VX wins when X = F
+    pub min_sound_timer: u8, // Sound timer values below this are ignored
+    pub i_mask: usize,      // I wraps to this many bits (0xFFF, 0xFFFF, or wider for bigger memory)
     pub load_store_offset: Option<usize>, // If set, mem load/store does I += (X + offset)
     pub dxy0_lores_width: Option<usize>, // If set, DXY0 draws an (width x 16) sprite
+    pub single_key_keypad: bool, // Keypad only reports the most recently pressed key
+
+    // Memory
+    pub memory_size: usize,
+    pub out_of_bounds: OutOfBounds, // What happens on accesses past the end of memory
+    pub read_only_interpreter: bool, // Should ignore ROM writes below pc_start (interpreter and fonts)
 
     // Misc
     pub pc_start: usize,
+    pub font: FontSet,
+    pub font_location: usize,
+    pub hires_entry: Option<usize>, // If set, "1260" at the start of the ROM enables hires and jumps here
+    pub display_location: Option<usize>, // If set, display is mirrored into RAM at this address
 
     // Resolutions
     // https://emulation.gametechwiki.com/index.php/Resolution#cite_note-CHIP-8_RES-1
     pub resolutions: Vec<(usize, usize)>, // TODO: Megachip?
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CPUVariant {
     Chip8,
     Chip48,
@@ -29,6 +60,12 @@ pub enum CPUVariant {
     SChipModern,
     SChipC,
     XOChip,
+    Chip8Hires,
+    Chip8E,
+    Chip8I,
+    Eti660,
+    Dream6800,
+    Chip8X,
 }
 
 impl CPUVariant {
@@ -41,6 +78,12 @@ impl CPUVariant {
             CPUVariant::SChipC => CPUVariant::into_schipc_config(),
             CPUVariant::SChipModern => CPUVariant::into_schip_modern_config(),
             CPUVariant::XOChip => CPUVariant::into_xo_chip_config(),
+            CPUVariant::Chip8Hires => CPUVariant::into_chip8_hires_config(),
+            CPUVariant::Chip8E => CPUVariant::into_chip8e_config(),
+            CPUVariant::Chip8I => CPUVariant::into_chip8i_config(),
+            CPUVariant::Eti660 => CPUVariant::into_eti660_config(),
+            CPUVariant::Dream6800 => CPUVariant::into_dream6800_config(),
+            CPUVariant::Chip8X => CPUVariant::into_chip8x_config(),
         }
     }
 
@@ -54,6 +97,9 @@ impl CPUVariant {
             logic_quirk: false,
             jump_quirk: true,
             load_store_offset: Some(0),
+            font: FontSet::SChip,
+            out_of_bounds: OutOfBounds::Clamp,
+            min_sound_timer: 0,
             ..Default::default()
         }
     }
@@ -67,7 +113,12 @@ impl CPUVariant {
             jump_quirk: true,
             load_store_offset: Some(0),
             dxy0_lores_width: Some(8),
+            row_collision_quirk: true,
+            hires_clear_quirk: false,
+            font: FontSet::SChip,
             resolutions: vec![(64, 32), (128, 64)],
+            out_of_bounds: OutOfBounds::Clamp,
+            min_sound_timer: 0,
             ..Default::default()
         }
     }
@@ -82,7 +133,12 @@ impl CPUVariant {
             jump_quirk: true,
             load_store_offset: None,
             dxy0_lores_width: Some(8),
+            row_collision_quirk: true,
+            hires_clear_quirk: false,
+            font: FontSet::SChip,
             resolutions: vec![(64, 32), (128, 64)],
+            out_of_bounds: OutOfBounds::Clamp,
+            min_sound_timer: 0,
             ..Default::default()
         }
     }
@@ -97,7 +153,10 @@ impl CPUVariant {
             vblank_quirk: false,
             scroll_quirk: true,
             dxy0_lores_width: Some(16),
+            font: FontSet::Octo,
+            font_location: 0x0, // Octo loads its font at the start of memory
             resolutions: vec![(64, 32), (128, 64)],
+            min_sound_timer: 0,
             ..Default::default()
         }
     }
@@ -115,7 +174,10 @@ impl CPUVariant {
             scroll_quirk: true,
             load_store_offset: None,
             dxy0_lores_width: Some(16),
+            font: FontSet::Octo,
+            font_location: 0x0, // Octo loads its font at the start of memory
             resolutions: vec![(64, 32), (128, 64)],
+            min_sound_timer: 0,
             ..Default::default()
         }
     }
@@ -128,11 +190,156 @@ impl CPUVariant {
             logic_quirk: false,
             vblank_quirk: false,
             scroll_quirk: true,
+            wrap_quirk: true,
             dxy0_lores_width: Some(16),
+            font: FontSet::Octo,
+            font_location: 0x0, // Octo loads its font at the start of memory
             resolutions: vec![(64, 32), (128, 64)],
+            memory_size: MEMORY_SIZE_XO_CHIP,
+            i_mask: 0xFFFF,
+            min_sound_timer: 0,
+            ..Default::default()
+        }
+    }
+
+    // Two page display, the ROM starts with "1260" which switches into 64x64
+    fn into_chip8_hires_config() -> CPUConfig {
+        CPUConfig {
+            hires_entry: Some(0x2C0),
+            resolutions: vec![(64, 64)],
+            ..Default::default()
+        }
+    }
+
+    fn into_chip8e_config() -> CPUConfig {
+        CPUConfig {
+            chip8e_enabled: true,
+            io_port_enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn into_chip8i_config() -> CPUConfig {
+        CPUConfig {
+            io_port_enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn into_eti660_config() -> CPUConfig {
+        CPUConfig {
+            pc_start: 0x600,
+            font: FontSet::Eti660,
+            resolutions: vec![(64, 48)],
+            min_sound_timer: 0,
+            ..Default::default()
+        }
+    }
+
+    // CHIPOS keeps the display buffer in RAM, right below the program
+    fn into_dream6800_config() -> CPUConfig {
+        CPUConfig {
+            single_key_keypad: true,
+            font: FontSet::Dream6800,
+            display_location: Some(0x100),
+            min_sound_timer: 0,
+            key_wait_on_press: true,
             ..Default::default()
         }
     }
+
+    // The VP-590 colour board moves programs up to 0x300
+    fn into_chip8x_config() -> CPUConfig {
+        CPUConfig {
+            color_enabled: true,
+            pc_start: 0x300,
+            ..Default::default()
+        }
+    }
+}
+
+// Per-ROM quirk overrides, applied on top of a variant's config
+// Unset fields keep the variant's default
+#[derive(Clone, Debug, Default)]
+pub struct QuirkOverrides {
+    pub logic_quirk: Option<bool>,
+    pub shift_quirk: Option<bool>,
+    pub jump_quirk: Option<bool>,
+    pub vblank_quirk: Option<bool>,
+    pub wrap_quirk: Option<bool>,
+    pub i_overflow_quirk: Option<bool>,
+    pub key_wait_on_press: Option<bool>,
+    pub flag_first_quirk: Option<bool>,
+    pub min_sound_timer: Option<u8>,
+    pub load_store_offset: Option<Option<usize>>,
+    pub memory_size: Option<usize>,
+    pub out_of_bounds: Option<OutOfBounds>,
+    pub read_only_interpreter: Option<bool>,
+}
+
+impl CPUConfig {
+    pub(super) fn apply_overrides(&mut self, overrides: &QuirkOverrides) {
+        if let Some(logic_quirk) = overrides.logic_quirk {
+            self.logic_quirk = logic_quirk;
+        }
+        if let Some(shift_quirk) = overrides.shift_quirk {
+            self.shift_quirk = shift_quirk;
+        }
+        if let Some(jump_quirk) = overrides.jump_quirk {
+            self.jump_quirk = jump_quirk;
+        }
+        if let Some(vblank_quirk) = overrides.vblank_quirk {
+            self.vblank_quirk = vblank_quirk;
+        }
+        if let Some(wrap_quirk) = overrides.wrap_quirk {
+            self.wrap_quirk = wrap_quirk;
+        }
+        if let Some(i_overflow_quirk) = overrides.i_overflow_quirk {
+            self.i_overflow_quirk = i_overflow_quirk;
+        }
+        if let Some(key_wait_on_press) = overrides.key_wait_on_press {
+            self.key_wait_on_press = key_wait_on_press;
+        }
+        if let Some(flag_first_quirk) = overrides.flag_first_quirk {
+            self.flag_first_quirk = flag_first_quirk;
+        }
+        if let Some(min_sound_timer) = overrides.min_sound_timer {
+            self.min_sound_timer = min_sound_timer;
+        }
+        if let Some(load_store_offset) = overrides.load_store_offset {
+            self.load_store_offset = load_store_offset;
+        }
+        if let Some(memory_size) = overrides.memory_size {
+            self.memory_size = memory_size;
+            // Widen I so it can reach all of the larger memory
+            self.i_mask = self.i_mask.max(memory_size.next_power_of_two() - 1);
+        }
+        if let Some(out_of_bounds) = overrides.out_of_bounds {
+            self.out_of_bounds = out_of_bounds;
+        }
+        if let Some(read_only_interpreter) = overrides.read_only_interpreter {
+            self.read_only_interpreter = read_only_interpreter;
+        }
+    }
+
+    // Every override set, recreates these quirks on a fresh CPU of the same variant
+    pub(super) fn overrides(&self) -> QuirkOverrides {
+        QuirkOverrides {
+            logic_quirk: Some(self.logic_quirk),
+            shift_quirk: Some(self.shift_quirk),
+            jump_quirk: Some(self.jump_quirk),
+            vblank_quirk: Some(self.vblank_quirk),
+            wrap_quirk: Some(self.wrap_quirk),
+            i_overflow_quirk: Some(self.i_overflow_quirk),
+            key_wait_on_press: Some(self.key_wait_on_press),
+            flag_first_quirk: Some(self.flag_first_quirk),
+            min_sound_timer: Some(self.min_sound_timer),
+            load_store_offset: Some(self.load_store_offset),
+            memory_size: Some(self.memory_size),
+            out_of_bounds: Some(self.out_of_bounds),
+            read_only_interpreter: Some(self.read_only_interpreter),
+        }
+    }
 }
 
 // Define config default as CHIP-8 params
@@ -142,14 +349,35 @@ impl Default for CPUConfig {
             hires_enabled: false,
             scrolling_enabled: false,
             flag_registers_enabled: false,
+            chip8e_enabled: false,
+            io_port_enabled: false,
+            color_enabled: false,
             logic_quirk: true,
             shift_quirk: false,
             jump_quirk: false,
             vblank_quirk: true,
             scroll_quirk: false,
+            wrap_quirk: false,
+            row_collision_quirk: false,
+            hires_clear_quirk: true,
+            i_overflow_quirk: false,
+            key_wait_on_press: false,
+            flag_first_quirk: false,
+            min_sound_timer: 2,
+            i_mask: 0xFFF,
             load_store_offset: Some(1),
             dxy0_lores_width: None,
+            single_key_keypad: false,
+            memory_size: MEMORY_SIZE_CHIP8,
+            out_of_bounds: OutOfBounds::Wrap,
+            read_only_interpreter: false,
             pc_start: 0x200,
+            font: FontSet::Vip,
+            // The original interpreters kept their digits in ROM, outside the 4 KiB a ROM
+            // can see, so they go where most emulators put them
+            font_location: 0x50,
+            hires_entry: None,
+            display_location: None,
             resolutions: vec![(64, 32)],
         }
     }
@@ -0,0 +1,110 @@
+use std::fmt;
+
+// Longest string PrintString will read from I
+const MAX_STRING: usize = 256;
+
+// What a 0NNN debug hook does, real interpreters treat these as machine code calls
+// so hooks should point at addresses the ROM never actually calls
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugAction {
+    Break,          // Pause the CPU until resumed
+    PrintRegisters, // Dump V0-VF, I, PC and the stack
+    PrintString,    // Null terminated string at I, %0-%F print a register and %I prints I
+    Assert,         // Memory at I holds [register, expected value], stop the run on a mismatch
+}
+
+impl DebugAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "break" => Some(DebugAction::Break),
+            "regs" => Some(DebugAction::PrintRegisters),
+            "print" => Some(DebugAction::PrintString),
+            "assert" => Some(DebugAction::Assert),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AssertFailure {
+    pub address: usize,
+    pub register: usize,
+    pub expected: u8,
+    pub actual: u8,
+}
+
+impl fmt::Display for AssertFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "assertion at {:#05X} failed: v{:X} is {:#04X}, expected {:#04X}",
+            self.address, self.register, self.actual, self.expected
+        )
+    }
+}
+
+// Where a break hook paused the CPU, for the frontend to report
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: usize,
+    pub registers: String,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Break at {:#05X}: {}", self.address, self.registers)
+    }
+}
+
+pub(super) fn format_registers(V: &[u8; 0x10], I: usize, pc: usize, stack: &[usize]) -> String {
+    let registers: Vec<_> = V
+        .iter()
+        .enumerate()
+        .map(|(index, value)| format!("v{:X}={:02X}", index, value))
+        .collect();
+    let stack: Vec<_> = stack
+        .iter()
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+
+    format!(
+        "{} i={:#05X} pc={:#05X} stack=[{}]",
+        registers.join(" "),
+        I,
+        pc,
+        stack.join(", ")
+    )
+}
+
+pub(super) fn format_string(memory: &[u8], V: &[u8; 0x10], I: usize) -> String {
+    let bytes: Vec<u8> = memory
+        .iter()
+        .skip(I)
+        .take(MAX_STRING)
+        .take_while(|&&byte| byte != 0)
+        .copied()
+        .collect();
+
+    let mut output = String::new();
+    let mut chars = bytes.iter().map(|&byte| byte as char).peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        match chars.peek().copied() {
+            Some('I') | Some('i') => output += &format!("{:#05X}", I),
+            Some(digit) if digit.is_ascii_hexdigit() => {
+                output += &V[digit.to_digit(16).unwrap() as usize].to_string()
+            }
+            Some('%') => output.push('%'),
+            _ => {
+                output.push('%');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    output
+}
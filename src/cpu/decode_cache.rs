@@ -0,0 +1,140 @@
+use super::CPU;
+#[cfg(feature = "jit")]
+use super::{
+    config::CPUConfig,
+    jit::{Block, Jit},
+};
+
+// Addresses past this always take the slow path, so MegaChip sized memory doesn't need a huge cache
+const MAX_CACHED_ADDRESS: usize = 0x10000;
+
+// Runs a decoded instruction, picked once per address by CPU::handler
+pub(super) type Handler = fn(&mut CPU, Operands);
+
+// Opcode split into its fields, done once when the instruction is first decoded
+#[derive(Clone, Copy)]
+pub(super) struct Operands {
+    pub opcode: u16,
+    x: u8,
+    y: u8,
+    nn: u8,
+}
+
+impl Operands {
+    pub fn new(opcode: u16) -> Self {
+        Self {
+            opcode,
+            x: (opcode >> 8) as u8 & 0xF,
+            y: (opcode >> 4) as u8 & 0xF,
+            nn: opcode as u8,
+        }
+    }
+
+    pub fn x(&self) -> usize {
+        self.x as usize
+    }
+
+    pub fn y(&self) -> usize {
+        self.y as usize
+    }
+
+    pub fn n(&self) -> usize {
+        (self.nn & 0xF) as usize
+    }
+
+    pub fn nn(&self) -> u8 {
+        self.nn
+    }
+
+    pub fn nnn(&self) -> usize {
+        (self.opcode & 0xFFF) as usize
+    }
+}
+
+// Decoded instructions by address, dropped whenever the memory under them changes
+// Compiled blocks live here too, so every write that drops decoded instructions drops them
+pub(super) struct DecodeCache {
+    enabled: bool,
+    entries: Vec<Option<(Handler, Operands)>>,
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
+}
+
+impl DecodeCache {
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            enabled: true,
+            entries: vec![None; memory_size.min(MAX_CACHED_ADDRESS)],
+            #[cfg(feature = "jit")]
+            jit: None,
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, jit: Option<Jit>) {
+        self.jit = jit;
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn block(&mut self, pc: usize, memory: &[u8], config: &CPUConfig) -> Option<Block> {
+        self.jit.as_mut()?.block(pc, memory, config)
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub fn get(&self, pc: usize) -> Option<(Handler, Operands)> {
+        self.entries.get(pc).copied().flatten()
+    }
+
+    // Both bytes have to be in range, fetches that wrap or fault aren't cached
+    pub fn insert(&mut self, pc: usize, handler: Handler, operands: Operands) {
+        if self.enabled && pc + 1 < self.entries.len() {
+            self.entries[pc] = Some((handler, operands));
+        }
+    }
+
+    // An instruction starting at either byte could include this address
+    pub fn invalidate(&mut self, address: usize) {
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.invalidate_range(address, 1);
+        }
+
+        for address in address.saturating_sub(1)..=address {
+            if let Some(entry) = self.entries.get_mut(address) {
+                *entry = None;
+            }
+        }
+    }
+
+    pub fn invalidate_range(&mut self, start: usize, len: usize) {
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.invalidate_range(start, len);
+        }
+
+        let start = start.saturating_sub(1).min(self.entries.len());
+        let end = (start + len + 1).min(self.entries.len());
+        self.entries[start..end].fill(None);
+    }
+
+    pub fn resize(&mut self, memory_size: usize) {
+        self.entries = vec![None; memory_size.min(MAX_CACHED_ADDRESS)];
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.reset(memory_size);
+        }
+    }
+
+    // Everything goes, and the JIT can compile the new code again
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.reset(self.entries.len());
+        }
+    }
+}
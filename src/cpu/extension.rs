@@ -0,0 +1,107 @@
+use std::ops::Range;
+
+use super::{decode_cache::DecodeCache, memory::Memory, Framebuffer, Observer};
+
+// Custom instructions for home-grown variants, registered with CPU::with_extensions
+// Extensions only see opcodes the variant doesn't already handle (e.g. 5XY4, EXnn or FXnn gaps)
+pub trait Extension {
+    // Whether this extension handles the opcode
+    fn claims(&self, opcode: u16) -> bool;
+
+    // PC already points past the instruction when this runs
+    fn execute(&mut self, opcode: u16, machine: &mut Machine);
+
+    // Octo style text for disassemblers, the raw opcode is shown otherwise
+    fn disassemble(&self, _opcode: u16) -> Option<String> {
+        None
+    }
+}
+
+// The parts of the CPU an extension is allowed to touch
+// Register and memory access goes through the same paths as built-in instructions
+pub struct Machine<'a> {
+    pub(super) V: &'a mut [u8; 0x10],
+    pub(super) I: &'a mut usize,
+    pub(super) pc: &'a mut usize,
+    pub(super) memory: &'a mut Memory,
+    pub(super) decode_cache: &'a mut DecodeCache,
+    pub(super) framebuffer: &'a mut Framebuffer,
+    pub(super) resolution: (usize, usize),
+    pub(super) opcode: u16,
+    pub(super) observers: &'a mut [Box<dyn Observer>],
+    // What changed, so the CPU can sync memory mapped displays (DREAM 6800) afterwards
+    pub(super) written: Option<Range<usize>>,
+    pub(super) drawn: bool,
+}
+
+impl Machine<'_> {
+    pub fn v(&self, x: usize) -> u8 {
+        self.V[x & 0xF]
+    }
+
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        let x = x & 0xF;
+        self.V[x] = value;
+        for observer in self.observers.iter_mut() {
+            observer.on_register_write(x, value, self.opcode);
+        }
+    }
+
+    pub fn i(&self) -> usize {
+        *self.I
+    }
+
+    pub fn set_i(&mut self, value: usize) {
+        *self.I = value;
+    }
+
+    pub fn pc(&self) -> usize {
+        *self.pc
+    }
+
+    pub fn set_pc(&mut self, value: usize) {
+        *self.pc = value;
+    }
+
+    // Skip the next instruction, like the built-in conditionals
+    pub fn skip(&mut self) {
+        *self.pc += 2;
+    }
+
+    pub fn read(&mut self, address: usize) -> u8 {
+        let value = self.memory.read(address);
+        for observer in self.observers.iter_mut() {
+            observer.on_memory_read(address, value, self.opcode);
+        }
+        value
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) {
+        if let Some(address) = self.memory.write(address, value) {
+            self.decode_cache.invalidate(address);
+            self.written = Some(match self.written.take() {
+                Some(written) => written.start.min(address)..written.end.max(address + 1),
+                None => address..address + 1,
+            });
+        }
+        for observer in self.observers.iter_mut() {
+            observer.on_memory_write(address, value, self.opcode);
+        }
+    }
+
+    // Size of the framebuffer in use, in display pixels (not scaled lores pixels)
+    pub fn resolution(&self) -> (usize, usize) {
+        self.resolution
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.framebuffer
+            .pixel(x % self.resolution.0, y % self.resolution.1)
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
+        self.framebuffer
+            .set_pixel(x % self.resolution.0, y % self.resolution.1, value);
+        self.drawn = true;
+    }
+}
@@ -0,0 +1,23 @@
+use std::fmt;
+
+use super::MemoryFault;
+
+// Why the CPU stopped on its own, the instruction at address didn't run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Memory(MemoryFault),
+    StackOverflow { address: usize }, // 2NNN with all 16 stack entries in use
+    StackUnderflow { address: usize }, // 00EE with nothing to return to
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Memory(fault) => fault.fmt(f),
+            Fault::StackOverflow { address } => write!(f, "stack overflow at {:#05X}", address),
+            Fault::StackUnderflow { address } => {
+                write!(f, "return with an empty stack at {:#05X}", address)
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{MAX_RESOLUTION_HEIGHT, MAX_RESOLUTION_WIDTH};
+
+pub const ROW_WORDS: usize = MAX_RESOLUTION_WIDTH / 64;
+
+// Display pixels packed one bit each, leftmost pixel in the top bit of the first word
+// Sprite rows, scrolls and collisions all work a word at a time
+pub struct Framebuffer {
+    rows: [[u64; ROW_WORDS]; MAX_RESOLUTION_HEIGHT],
+    width: usize,
+    height: usize,
+    dirty: bool, // Changed since the frontend last drew it
+}
+
+impl Framebuffer {
+    pub(super) fn new(width: usize, height: usize) -> Self {
+        Self {
+            rows: [[0; ROW_WORDS]; MAX_RESOLUTION_HEIGHT],
+            width,
+            height,
+            dirty: true,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.rows[y][x / 64] & (1 << (63 - x % 64)) != 0
+    }
+
+    // Packed pixels for a row, only the words covering the display width
+    pub fn row(&self, y: usize) -> &[u64] {
+        &self.rows[y][..self.width.div_ceil(64)]
+    }
+
+    // Rows of pixels, for Video backends
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = bool> + '_> + '_ {
+        (0..self.height).map(move |y| (0..self.width).map(move |x| self.pixel(x, y)))
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(super) fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
+    pub(super) fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
+        let bit = 1 << (63 - x % 64);
+        match value {
+            true => self.rows[y][x / 64] |= bit,
+            false => self.rows[y][x / 64] &= !bit,
+        }
+        self.dirty = true;
+    }
+
+    pub(super) fn clear(&mut self) {
+        for row in &mut self.rows[..self.height] {
+            row.fill(0);
+        }
+        self.dirty = true;
+    }
+
+    // XOR a sprite row in, true if it turned any pixel off
+    pub(super) fn xor_row(&mut self, y: usize, mask: &[u64; ROW_WORDS]) -> bool {
+        let row = &mut self.rows[y];
+        let mut collided = false;
+        for (word, mask) in row.iter_mut().zip(mask) {
+            collided |= *word & mask != 0;
+            *word ^= mask;
+        }
+        self.dirty = true;
+        collided
+    }
+
+    pub(super) fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.height);
+        self.rows.copy_within(..self.height - n, n);
+        for row in &mut self.rows[..n] {
+            row.fill(0);
+        }
+        self.dirty = true;
+    }
+
+    pub(super) fn scroll_right(&mut self, n: usize) {
+        for row in &mut self.rows[..self.height] {
+            *row = shift_right(row, n);
+        }
+        self.clip();
+    }
+
+    pub(super) fn scroll_left(&mut self, n: usize) {
+        for row in &mut self.rows[..self.height] {
+            *row = shift_left(row, n);
+        }
+        self.clip();
+    }
+
+    // Pixels pushed past the right edge are gone, not kept off-screen
+    fn clip(&mut self) {
+        let visible = first_pixels(self.width);
+        for row in &mut self.rows[..self.height] {
+            for (word, visible) in row.iter_mut().zip(visible) {
+                *word &= visible;
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+// Row mask covering the first `width` pixels
+fn first_pixels(width: usize) -> [u64; ROW_WORDS] {
+    let mut mask = [0; ROW_WORDS];
+    for (index, word) in mask.iter_mut().enumerate() {
+        *word = match width.saturating_sub(index * 64) {
+            0 => 0,
+            bits if bits >= 64 => u64::MAX,
+            bits => !(u64::MAX >> bits),
+        };
+    }
+    mask
+}
+
+// Places `width` bits (MSB first, up to 128) on a row mask starting at pixel `x`
+// Anything past the end of the row is dropped
+pub(super) fn place(mask: &mut [u64; ROW_WORDS], bits: u128, width: usize, x: usize) {
+    if width == 0 || x >= MAX_RESOLUTION_WIDTH {
+        return;
+    }
+
+    let aligned = bits << (128 - width);
+    let (word, offset) = (x / 64, x % 64);
+    let shifted = [
+        (aligned >> (64 + offset)) as u64,
+        (aligned >> offset) as u64,
+        match offset {
+            0 => 0,
+            _ => (aligned << (64 - offset)) as u64,
+        },
+    ];
+    for (index, part) in shifted.into_iter().enumerate() {
+        if let Some(target) = mask.get_mut(word + index) {
+            *target |= part;
+        }
+    }
+}
+
+fn shift_right(row: &[u64; ROW_WORDS], n: usize) -> [u64; ROW_WORDS] {
+    let mut shifted = [0; ROW_WORDS];
+    let (words, bits) = (n / 64, n % 64);
+    for (index, shifted) in shifted.iter_mut().enumerate().skip(words) {
+        let source = index - words;
+        *shifted = match bits {
+            0 => row[source],
+            _ if source == 0 => row[source] >> bits,
+            _ => (row[source] >> bits) | (row[source - 1] << (64 - bits)),
+        };
+    }
+    shifted
+}
+
+fn shift_left(row: &[u64; ROW_WORDS], n: usize) -> [u64; ROW_WORDS] {
+    let mut shifted = [0; ROW_WORDS];
+    let (words, bits) = (n / 64, n % 64);
+    for (index, shifted) in shifted
+        .iter_mut()
+        .enumerate()
+        .take(ROW_WORDS.saturating_sub(words))
+    {
+        let source = index + words;
+        *shifted = match bits {
+            0 => row[source],
+            _ if source + 1 == ROW_WORDS => row[source] << bits,
+            _ => (row[source] << bits) | (row[source + 1] >> (64 - bits)),
+        };
+    }
+    shifted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pixels of a row mask, one bool each, to check the word-at-a-time code against
+    fn pixels(row: &[u64; ROW_WORDS]) -> Vec<bool> {
+        (0..MAX_RESOLUTION_WIDTH)
+            .map(|x| row[x / 64] & (1 << (63 - x % 64)) != 0)
+            .collect()
+    }
+
+    fn pattern(seed: u32) -> [u64; ROW_WORDS] {
+        let mut row = [0; ROW_WORDS];
+        for (index, word) in row.iter_mut().enumerate() {
+            *word =
+                0x0123_4567_89AB_CDEFu64.rotate_left(seed * 7 + index as u32 * 13) ^ seed as u64;
+        }
+        row
+    }
+
+    #[test]
+    fn place_matches_pixels() {
+        let bits: u128 = 0xF0F0_1234_5678_9ABC_DEF0_0FED_CBA9_8765;
+        for width in [1, 8, 16, 64, 100, 128] {
+            for x in [0, 1, 7, 56, 60, 63, 64, 65, 127, 128, 200, 250, 255] {
+                let mut mask = [0; ROW_WORDS];
+                place(&mut mask, bits & (u128::MAX >> (128 - width)), width, x);
+
+                let expected: Vec<bool> = (0..MAX_RESOLUTION_WIDTH)
+                    .map(|pixel| {
+                        (x..x + width).contains(&pixel)
+                            && bits >> (width - 1 - (pixel - x)) & 1 == 1
+                    })
+                    .collect();
+                assert_eq!(pixels(&mask), expected, "width {} at {}", width, x);
+            }
+        }
+    }
+
+    #[test]
+    fn shifts_match_pixels() {
+        for n in [0, 1, 4, 8, 63, 64, 65, 100, 128, 200, 255, 256] {
+            let row = pattern(n as u32);
+            let before = pixels(&row);
+
+            let right: Vec<bool> = (0..MAX_RESOLUTION_WIDTH)
+                .map(|x| x >= n && before[x - n])
+                .collect();
+            assert_eq!(pixels(&shift_right(&row, n)), right, "right {}", n);
+
+            let left: Vec<bool> = (0..MAX_RESOLUTION_WIDTH)
+                .map(|x| x + n < MAX_RESOLUTION_WIDTH && before[x + n])
+                .collect();
+            assert_eq!(pixels(&shift_left(&row, n)), left, "left {}", n);
+        }
+    }
+
+    #[test]
+    fn scrolls_clip_to_width() {
+        let mut framebuffer = Framebuffer::new(128, 64);
+        framebuffer.rows[0] = [u64::MAX; ROW_WORDS];
+        framebuffer.scroll_right(4);
+
+        let row = pixels(&framebuffer.rows[0]);
+        assert_eq!(row.iter().filter(|&&pixel| pixel).count(), 124);
+        assert!(row[4..128].iter().all(|&pixel| pixel));
+        assert!(!row[128..].iter().any(|&pixel| pixel));
+    }
+
+    #[test]
+    fn xor_row_reports_collisions() {
+        let mut framebuffer = Framebuffer::new(128, 64);
+        let mut mask = [0; ROW_WORDS];
+        place(&mut mask, 0xFF, 8, 60);
+
+        assert!(!framebuffer.xor_row(0, &mask));
+        assert!(framebuffer.pixel(63, 0) && framebuffer.pixel(64, 0));
+
+        // Overlapping only in the second word still collides
+        let mut other = [0; ROW_WORDS];
+        place(&mut other, 0x1, 1, 67);
+        assert!(framebuffer.xor_row(0, &other));
+        assert!(!framebuffer.pixel(67, 0));
+
+        let mut apart = [0; ROW_WORDS];
+        place(&mut apart, 0xFF, 8, 100);
+        assert!(!framebuffer.xor_row(0, &apart));
+    }
+
+    #[test]
+    fn scroll_down_moves_rows() {
+        let mut framebuffer = Framebuffer::new(128, 64);
+        for y in 0..64 {
+            framebuffer.rows[y] = pattern(y as u32);
+        }
+        framebuffer.scroll_down(5);
+
+        assert!((0..5).all(|y| framebuffer.rows[y] == [0; ROW_WORDS]));
+        assert!((5..64).all(|y| framebuffer.rows[y] == pattern(y as u32 - 5)));
+
+        framebuffer.scroll_down(100);
+        assert!((0..64).all(|y| framebuffer.rows[y] == [0; ROW_WORDS]));
+    }
+}
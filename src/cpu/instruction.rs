@@ -0,0 +1,283 @@
+use std::fmt;
+
+// Decoded form of every opcode known across the supported variants
+// Decoding doesn't check whether the current variant enables an opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16),           // 0NNN
+    ScrollDown(u8),     // 00CN
+    ScrollUp(u8),       // 00DN (XO-CHIP)
+    Clear,              // 00E0
+    Return,             // 00EE
+    ScrollRight,        // 00FB
+    ScrollLeft,         // 00FC
+    Exit,               // 00FD
+    Lores,              // 00FE
+    Hires,              // 00FF
+    Jump(u16),          // 1NNN
+    Call(u16),          // 2NNN
+    SkipEqImm(u8, u8),  // 3XNN
+    SkipNeImm(u8, u8),  // 4XNN
+    SkipEqReg(u8, u8),  // 5XY0
+    SaveRange(u8, u8),  // 5XY2 (XO-CHIP)
+    LoadRange(u8, u8),  // 5XY3 (XO-CHIP)
+    SetImm(u8, u8),     // 6XNN
+    AddImm(u8, u8),     // 7XNN
+    Move(u8, u8),       // 8XY0
+    Or(u8, u8),         // 8XY1
+    And(u8, u8),        // 8XY2
+    Xor(u8, u8),        // 8XY3
+    Add(u8, u8),        // 8XY4
+    Sub(u8, u8),        // 8XY5
+    ShiftRight(u8, u8), // 8XY6
+    SubReverse(u8, u8), // 8XY7
+    ShiftLeft(u8, u8),  // 8XYE
+    SkipNeReg(u8, u8),  // 9XY0
+    SetI(u16),          // ANNN
+    JumpOffset(u16),    // BNNN
+    Random(u8, u8),     // CXNN
+    Draw(u8, u8, u8),   // DXYN
+    SkipKey(u8),        // EX9E
+    SkipNotKey(u8),     // EXA1
+    SetILong,           // F000 NNNN (XO-CHIP)
+    Plane(u8),          // FN01 (XO-CHIP)
+    Audio,              // F002 (XO-CHIP)
+    GetDelay(u8),       // FX07
+    WaitKey(u8),        // FX0A
+    SetDelay(u8),       // FX15
+    SetSound(u8),       // FX18
+    AddI(u8),           // FX1E
+    SetISprite(u8),     // FX29
+    SetIBigSprite(u8),  // FX30
+    Bcd(u8),            // FX33
+    Pitch(u8),          // FX3A (XO-CHIP)
+    Store(u8),          // FX55
+    Load(u8),           // FX65
+    StoreFlags(u8),     // FX75
+    LoadFlags(u8),      // FX85
+    Custom(u16),        // Claimed by an extension, see CPU::decode
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Self {
+        let (b1, x, y, n) = (
+            (opcode >> 12) as u8,
+            (opcode >> 8) as u8 & 0xF,
+            (opcode >> 4) as u8 & 0xF,
+            opcode as u8 & 0xF,
+        );
+        let nn = opcode as u8;
+        let nnn = opcode & 0xFFF;
+
+        match b1 {
+            0 => match nnn {
+                0x0E0 => Instruction::Clear,
+                0x0EE => Instruction::Return,
+                0x0FB => Instruction::ScrollRight,
+                0x0FC => Instruction::ScrollLeft,
+                0x0FD => Instruction::Exit,
+                0x0FE => Instruction::Lores,
+                0x0FF => Instruction::Hires,
+                _ if nnn & 0xFF0 == 0x0C0 => Instruction::ScrollDown(n),
+                _ if nnn & 0xFF0 == 0x0D0 => Instruction::ScrollUp(n),
+                _ => Instruction::Sys(nnn),
+            },
+            1 => Instruction::Jump(nnn),
+            2 => Instruction::Call(nnn),
+            3 => Instruction::SkipEqImm(x, nn),
+            4 => Instruction::SkipNeImm(x, nn),
+            5 => match n {
+                0 => Instruction::SkipEqReg(x, y),
+                2 => Instruction::SaveRange(x, y),
+                3 => Instruction::LoadRange(x, y),
+                _ => Instruction::Unknown(opcode),
+            },
+            6 => Instruction::SetImm(x, nn),
+            7 => Instruction::AddImm(x, nn),
+            8 => match n {
+                0 => Instruction::Move(x, y),
+                1 => Instruction::Or(x, y),
+                2 => Instruction::And(x, y),
+                3 => Instruction::Xor(x, y),
+                4 => Instruction::Add(x, y),
+                5 => Instruction::Sub(x, y),
+                6 => Instruction::ShiftRight(x, y),
+                7 => Instruction::SubReverse(x, y),
+                0xE => Instruction::ShiftLeft(x, y),
+                _ => Instruction::Unknown(opcode),
+            },
+            9 if n == 0 => Instruction::SkipNeReg(x, y),
+            0xA => Instruction::SetI(nnn),
+            0xB => Instruction::JumpOffset(nnn),
+            0xC => Instruction::Random(x, nn),
+            0xD => Instruction::Draw(x, y, n),
+            0xE => match nn {
+                0x9E => Instruction::SkipKey(x),
+                0xA1 => Instruction::SkipNotKey(x),
+                _ => Instruction::Unknown(opcode),
+            },
+            0xF => match nn {
+                0x00 if x == 0 => Instruction::SetILong,
+                0x01 => Instruction::Plane(x),
+                0x02 if x == 0 => Instruction::Audio,
+                0x07 => Instruction::GetDelay(x),
+                0x0A => Instruction::WaitKey(x),
+                0x15 => Instruction::SetDelay(x),
+                0x18 => Instruction::SetSound(x),
+                0x1E => Instruction::AddI(x),
+                0x29 => Instruction::SetISprite(x),
+                0x30 => Instruction::SetIBigSprite(x),
+                0x33 => Instruction::Bcd(x),
+                0x3A => Instruction::Pitch(x),
+                0x55 => Instruction::Store(x),
+                0x65 => Instruction::Load(x),
+                0x75 => Instruction::StoreFlags(x),
+                0x85 => Instruction::LoadFlags(x),
+                _ => Instruction::Unknown(opcode),
+            },
+            _ => Instruction::Unknown(opcode),
+        }
+    }
+
+    // Size in bytes, F000 NNNN is the only 4 byte instruction
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::SetILong => 4,
+            _ => 2,
+        }
+    }
+
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipEqImm(..)
+                | Instruction::SkipNeImm(..)
+                | Instruction::SkipEqReg(..)
+                | Instruction::SkipNeReg(..)
+                | Instruction::SkipKey(_)
+                | Instruction::SkipNotKey(_)
+        )
+    }
+
+    // Octo syntax with the operands left out, for grouping instructions together
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Sys(_) => "native NNN",
+            Instruction::ScrollDown(_) => "scroll-down N",
+            Instruction::ScrollUp(_) => "scroll-up N",
+            Instruction::Clear => "clear",
+            Instruction::Return => "return",
+            Instruction::ScrollRight => "scroll-right",
+            Instruction::ScrollLeft => "scroll-left",
+            Instruction::Exit => "exit",
+            Instruction::Lores => "lores",
+            Instruction::Hires => "hires",
+            Instruction::Jump(_) => "jump NNN",
+            Instruction::Call(_) => "call NNN",
+            Instruction::SkipEqImm(..) => "if vX != NN then",
+            Instruction::SkipNeImm(..) => "if vX == NN then",
+            Instruction::SkipEqReg(..) => "if vX != vY then",
+            Instruction::SaveRange(..) => "save vX - vY",
+            Instruction::LoadRange(..) => "load vX - vY",
+            Instruction::SetImm(..) => "vX := NN",
+            Instruction::AddImm(..) => "vX += NN",
+            Instruction::Move(..) => "vX := vY",
+            Instruction::Or(..) => "vX |= vY",
+            Instruction::And(..) => "vX &= vY",
+            Instruction::Xor(..) => "vX ^= vY",
+            Instruction::Add(..) => "vX += vY",
+            Instruction::Sub(..) => "vX -= vY",
+            Instruction::ShiftRight(..) => "vX >>= vY",
+            Instruction::SubReverse(..) => "vX =- vY",
+            Instruction::ShiftLeft(..) => "vX <<= vY",
+            Instruction::SkipNeReg(..) => "if vX == vY then",
+            Instruction::SetI(_) => "i := NNN",
+            Instruction::JumpOffset(_) => "jump0 NNN",
+            Instruction::Random(..) => "vX := random NN",
+            Instruction::Draw(..) => "sprite vX vY N",
+            Instruction::SkipKey(_) => "if vX -key then",
+            Instruction::SkipNotKey(_) => "if vX key then",
+            Instruction::SetILong => "i := long",
+            Instruction::Plane(_) => "plane N",
+            Instruction::Audio => "audio",
+            Instruction::GetDelay(_) => "vX := delay",
+            Instruction::WaitKey(_) => "vX := key",
+            Instruction::SetDelay(_) => "delay := vX",
+            Instruction::SetSound(_) => "buzzer := vX",
+            Instruction::AddI(_) => "i += vX",
+            Instruction::SetISprite(_) => "i := hex vX",
+            Instruction::SetIBigSprite(_) => "i := bighex vX",
+            Instruction::Bcd(_) => "bcd vX",
+            Instruction::Pitch(_) => "pitch := vX",
+            Instruction::Store(_) => "save vX",
+            Instruction::Load(_) => "load vX",
+            Instruction::StoreFlags(_) => "saveflags vX",
+            Instruction::LoadFlags(_) => "loadflags vX",
+            Instruction::Custom(_) => "custom",
+            Instruction::Unknown(_) => "unknown",
+        }
+    }
+}
+
+// Octo-style mnemonics
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "native {:#05X}", nnn),
+            Instruction::ScrollDown(n) => write!(f, "scroll-down {}", n),
+            Instruction::ScrollUp(n) => write!(f, "scroll-up {}", n),
+            Instruction::Clear => write!(f, "clear"),
+            Instruction::Return => write!(f, "return"),
+            Instruction::ScrollRight => write!(f, "scroll-right"),
+            Instruction::ScrollLeft => write!(f, "scroll-left"),
+            Instruction::Exit => write!(f, "exit"),
+            Instruction::Lores => write!(f, "lores"),
+            Instruction::Hires => write!(f, "hires"),
+            Instruction::Jump(nnn) => write!(f, "jump {:#05X}", nnn),
+            Instruction::Call(nnn) => write!(f, "call {:#05X}", nnn),
+            Instruction::SkipEqImm(x, nn) => write!(f, "if v{:X} != {:#04X} then", x, nn),
+            Instruction::SkipNeImm(x, nn) => write!(f, "if v{:X} == {:#04X} then", x, nn),
+            Instruction::SkipEqReg(x, y) => write!(f, "if v{:X} != v{:X} then", x, y),
+            Instruction::SaveRange(x, y) => write!(f, "save v{:X} - v{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "load v{:X} - v{:X}", x, y),
+            Instruction::SetImm(x, nn) => write!(f, "v{:X} := {:#04X}", x, nn),
+            Instruction::AddImm(x, nn) => write!(f, "v{:X} += {:#04X}", x, nn),
+            Instruction::Move(x, y) => write!(f, "v{:X} := v{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "v{:X} |= v{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "v{:X} &= v{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "v{:X} ^= v{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "v{:X} += v{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "v{:X} -= v{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "v{:X} >>= v{:X}", x, y),
+            Instruction::SubReverse(x, y) => write!(f, "v{:X} =- v{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "v{:X} <<= v{:X}", x, y),
+            Instruction::SkipNeReg(x, y) => write!(f, "if v{:X} == v{:X} then", x, y),
+            Instruction::SetI(nnn) => write!(f, "i := {:#05X}", nnn),
+            Instruction::JumpOffset(nnn) => write!(f, "jump0 {:#05X}", nnn),
+            Instruction::Random(x, nn) => write!(f, "v{:X} := random {:#04X}", x, nn),
+            Instruction::Draw(x, y, n) => write!(f, "sprite v{:X} v{:X} {}", x, y, n),
+            Instruction::SkipKey(x) => write!(f, "if v{:X} -key then", x),
+            Instruction::SkipNotKey(x) => write!(f, "if v{:X} key then", x),
+            Instruction::SetILong => write!(f, "i := long"),
+            Instruction::Plane(n) => write!(f, "plane {}", n),
+            Instruction::Audio => write!(f, "audio"),
+            Instruction::GetDelay(x) => write!(f, "v{:X} := delay", x),
+            Instruction::WaitKey(x) => write!(f, "v{:X} := key", x),
+            Instruction::SetDelay(x) => write!(f, "delay := v{:X}", x),
+            Instruction::SetSound(x) => write!(f, "buzzer := v{:X}", x),
+            Instruction::AddI(x) => write!(f, "i += v{:X}", x),
+            Instruction::SetISprite(x) => write!(f, "i := hex v{:X}", x),
+            Instruction::SetIBigSprite(x) => write!(f, "i := bighex v{:X}", x),
+            Instruction::Bcd(x) => write!(f, "bcd v{:X}", x),
+            Instruction::Pitch(x) => write!(f, "pitch := v{:X}", x),
+            Instruction::Store(x) => write!(f, "save v{:X}", x),
+            Instruction::Load(x) => write!(f, "load v{:X}", x),
+            Instruction::StoreFlags(x) => write!(f, "saveflags v{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "loadflags v{:X}", x),
+            Instruction::Custom(opcode) | Instruction::Unknown(opcode) => {
+                write!(f, "{:#06X}", opcode)
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+
+use super::{
+    config::CPUConfig,
+    recompiler::{classify, Kind, MAX_BLOCK_LEN},
+};
+
+// Times a block has to start before it gets compiled
+const HOT_THRESHOLD: u16 = 16;
+
+// Same limit as the decode cache, blocks past this are always interpreted
+const MAX_COMPILED_ADDRESS: usize = 0x10000;
+
+// Takes V and I, returns the new PC
+type BlockFn = unsafe extern "C" fn(*mut u8, *mut usize) -> usize;
+
+#[derive(Clone, Copy)]
+pub(super) struct Block {
+    function: BlockFn,
+    pub len: usize,       // Instructions, a block is always run to the end
+    pub last_opcode: u16, // What CPU::opcode ends up as
+    end: usize,           // Address past the last instruction
+}
+
+impl Block {
+    // Safe as long as the block was compiled for this CPU's memory and config
+    pub fn run(&self, V: &mut [u8; 0x10], I: &mut usize) -> usize {
+        unsafe { (self.function)(V.as_mut_ptr(), I) }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Cold(u16),       // Times reached so far
+    Compiled(Block), // Starts here
+    Interpret,       // First instruction can't be compiled, or the code has been written to
+}
+
+// Compiles straight-line runs of register-only instructions (6XNN, 7XNN, 8XYN, ANNN, FX1E)
+// ending in a jump or skip. Anything touching memory, the screen, timers, keys or the stack
+// (DXYN, FX0A, FX55...) ends the block and is left to the interpreter
+pub(super) struct Jit {
+    module: JITModule,
+    entries: Vec<Entry>,
+}
+
+impl Jit {
+    pub fn new(memory_size: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed")?;
+        let isa = cranelift_native::builder()?.finish(settings::Flags::new(flags))?;
+
+        Ok(Self {
+            module: JITModule::new(JITBuilder::with_isa(
+                isa,
+                cranelift_module::default_libcall_names(),
+            )),
+            entries: vec![Entry::Cold(0); memory_size.min(MAX_COMPILED_ADDRESS)],
+        })
+    }
+
+    // Compiled block at pc, compiling it first if it just became hot
+    pub fn block(&mut self, pc: usize, memory: &[u8], config: &CPUConfig) -> Option<Block> {
+        match self.entries.get(pc)? {
+            Entry::Compiled(block) => Some(*block),
+            Entry::Interpret => None,
+            Entry::Cold(count) if count + 1 < HOT_THRESHOLD => {
+                self.entries[pc] = Entry::Cold(count + 1);
+                None
+            }
+            Entry::Cold(_) => {
+                let block = self.compile(pc, memory, config);
+                self.entries[pc] = match block {
+                    Some(block) => Entry::Compiled(block),
+                    None => Entry::Interpret,
+                };
+                block
+            }
+        }
+    }
+
+    // Drops any block covering the range, and never compiles that code again
+    // Cranelift can't free the old code, self-modifying loops would leak it every pass
+    pub fn invalidate_range(&mut self, start: usize, len: usize) {
+        let end = (start + len).min(self.entries.len());
+        let first = start.saturating_sub(MAX_BLOCK_LEN * 2);
+        for pc in first..end {
+            if let Entry::Compiled(block) = self.entries[pc] {
+                if block.end > start {
+                    self.entries[pc] = Entry::Interpret;
+                }
+            }
+        }
+        for pc in start.saturating_sub(1).min(end)..end {
+            self.entries[pc] = Entry::Interpret;
+        }
+    }
+
+    // For ROM and font loads, code there hasn't run yet so it can still be compiled
+    pub fn reset(&mut self, memory_size: usize) {
+        self.entries = vec![Entry::Cold(0); memory_size.min(MAX_COMPILED_ADDRESS)];
+    }
+
+    fn compile(&mut self, start: usize, memory: &[u8], config: &CPUConfig) -> Option<Block> {
+        let limit = memory.len().min(self.entries.len());
+        let mut opcodes = vec![];
+        for pc in (start..).step_by(2).take(MAX_BLOCK_LEN) {
+            // Fetches past the end wrap, fault or get clamped, leave that to the interpreter
+            // Code that has been written to isn't compiled either
+            if pc + 1 >= limit
+                || (pc > start && matches!(self.entries[pc], Entry::Interpret))
+                || matches!(self.entries[pc + 1], Entry::Interpret)
+            {
+                break;
+            }
+
+            let opcode = ((memory[pc] as u16) << 8) | memory[pc + 1] as u16;
+            match classify(opcode, config) {
+                Kind::Unsupported => break,
+                Kind::Straight => opcodes.push(opcode),
+                Kind::Terminator => {
+                    opcodes.push(opcode);
+                    break;
+                }
+            }
+        }
+
+        let &last_opcode = opcodes.last()?;
+        let function = self.emit(start, &opcodes, config).ok()?;
+        Some(Block {
+            function,
+            len: opcodes.len(),
+            last_opcode,
+            end: start + opcodes.len() * 2,
+        })
+    }
+
+    fn emit(
+        &mut self,
+        start: usize,
+        opcodes: &[u16],
+        config: &CPUConfig,
+    ) -> Result<BlockFn, Box<dyn std::error::Error>> {
+        let pointer = self.module.target_config().pointer_type();
+
+        let mut context = self.module.make_context();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.returns.push(AbiParam::new(pointer));
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut b = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        b.seal_block(entry);
+
+        let (v_ptr, i_ptr) = (b.block_params(entry)[0], b.block_params(entry)[1]);
+        let flags = MemFlags::trusted();
+
+        // Everything lives in SSA values, loaded once on entry and stored back on exit
+        let mut V: Vec<Value> = (0..0x10)
+            .map(|x| b.ins().load(types::I8, flags, v_ptr, x))
+            .collect();
+        let mut I = b.ins().load(pointer, flags, i_ptr, 0);
+
+        let mut next_pc = None;
+        for (index, &opcode) in opcodes.iter().enumerate() {
+            let pc = (start + index * 2) as i64;
+            let x = (opcode >> 8) as usize & 0xF;
+            let y = (opcode >> 4) as usize & 0xF;
+            let nn = (opcode & 0xFF) as i64;
+            let nnn = (opcode & 0xFFF) as i64;
+
+            match (opcode >> 12, opcode & 0xF) {
+                (1, _) => next_pc = Some(b.ins().iconst(pointer, nnn)),
+                (3 | 4 | 5 | 9, _) => {
+                    let check = match opcode >> 12 {
+                        3 | 4 => b.ins().iconst(types::I8, nn),
+                        _ => V[y],
+                    };
+                    let cc = match opcode >> 12 {
+                        3 | 5 => IntCC::Equal,
+                        _ => IntCC::NotEqual,
+                    };
+                    let skip = b.ins().icmp(cc, V[x], check);
+                    let (taken, not_taken) = (
+                        b.ins().iconst(pointer, pc + 4),
+                        b.ins().iconst(pointer, pc + 2),
+                    );
+                    next_pc = Some(b.ins().select(skip, taken, not_taken));
+                }
+                (6, _) => V[x] = b.ins().iconst(types::I8, nn),
+                (7, _) => V[x] = b.ins().iadd_imm(V[x], nn),
+                (8, code) => {
+                    let (vx, vy) = (V[x], V[y]);
+                    let shifted = if config.shift_quirk { vx } else { vy };
+                    let (result, flag) = match code {
+                        0 => (vy, None),
+                        1 => (b.ins().bor(vx, vy), None),
+                        2 => (b.ins().band(vx, vy), None),
+                        3 => (b.ins().bxor(vx, vy), None),
+                        4 => {
+                            let result = b.ins().iadd(vx, vy);
+                            (
+                                result,
+                                Some(b.ins().icmp(IntCC::UnsignedLessThan, result, vx)),
+                            )
+                        }
+                        5 => (
+                            b.ins().isub(vx, vy),
+                            Some(b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, vx, vy)),
+                        ),
+                        6 => (
+                            b.ins().ushr_imm(shifted, 1),
+                            Some(b.ins().band_imm(shifted, 1)),
+                        ),
+                        7 => (
+                            b.ins().isub(vy, vx),
+                            Some(b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, vy, vx)),
+                        ),
+                        _ => (
+                            b.ins().ishl_imm(shifted, 1),
+                            Some(b.ins().ushr_imm(shifted, 7)),
+                        ),
+                    };
+
+                    match flag {
+                        // Same write order as CPU::set_with_flag
+                        Some(flag) if config.flag_first_quirk => {
+                            V[0xF] = flag;
+                            V[x] = result;
+                        }
+                        Some(flag) => {
+                            V[x] = result;
+                            V[0xF] = flag;
+                        }
+                        None => {
+                            V[x] = result;
+                            if (1..=3).contains(&code) && config.logic_quirk {
+                                V[0xF] = b.ins().iconst(types::I8, 0);
+                            }
+                        }
+                    }
+                }
+                (0xA, _) => I = b.ins().iconst(pointer, nnn),
+                (0xB, _) => {
+                    let offset = match config.jump_quirk {
+                        true => V[(nnn as usize >> 8) & 0xF],
+                        false => V[0],
+                    };
+                    let offset = b.ins().uextend(pointer, offset);
+                    next_pc = Some(b.ins().iadd_imm(offset, nnn));
+                }
+                _ => {
+                    // FX1E
+                    let vx = b.ins().uextend(pointer, V[x]);
+                    let sum = b.ins().iadd(I, vx);
+                    if config.i_overflow_quirk {
+                        V[0xF] = b.ins().icmp_imm(IntCC::UnsignedGreaterThan, sum, 0xFFF);
+                    }
+                    I = b.ins().band_imm(sum, config.i_mask as i64);
+                }
+            }
+        }
+
+        // Blocks cut short by an instruction the JIT doesn't handle carry on after the last one
+        let next_pc = match next_pc {
+            Some(next_pc) => next_pc,
+            None => b.ins().iconst(pointer, (start + opcodes.len() * 2) as i64),
+        };
+
+        for (x, &value) in V.iter().enumerate() {
+            b.ins().store(flags, value, v_ptr, x as i32);
+        }
+        b.ins().store(flags, I, i_ptr, 0);
+        b.ins().return_(&[next_pc]);
+        b.finalize();
+
+        let id = self
+            .module
+            .declare_anonymous_function(&context.func.signature)?;
+        self.module.define_function(id, &mut context)?;
+        self.module.clear_context(&mut context);
+        self.module.finalize_definitions()?;
+
+        let code = self.module.get_finalized_function(id);
+        Ok(unsafe { std::mem::transmute::<*const u8, BlockFn>(code) })
+    }
+}
@@ -0,0 +1,124 @@
+use std::{fmt, ops::Range};
+
+pub const MEMORY_SIZE_CHIP8: usize = 0x1000; // 4 KiB
+pub const MEMORY_SIZE_XO_CHIP: usize = 0x10000; // 64 KiB
+pub const MEMORY_SIZE_MEGACHIP: usize = 0x1000000; // 16 MiB
+
+// What happens when the ROM accesses an address past the end of memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfBounds {
+    Wrap,  // Mirror memory, like partial address decoding on real hardware
+    Clamp, // Stick to the last byte
+    Fault, // Stop the CPU
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryFault {
+    pub address: usize,
+    pub write: bool,
+}
+
+impl fmt::Display for MemoryFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.write {
+            true => write!(f, "invalid memory write at {:#X}", self.address),
+            false => write!(f, "invalid memory read at {:#X}", self.address),
+        }
+    }
+}
+
+// RAM behind a bus, every access from the ROM goes through read/write
+pub(super) struct Memory {
+    bytes: Vec<u8>,
+    out_of_bounds: OutOfBounds,
+    read_only: Option<Range<usize>>,
+    fault: Option<MemoryFault>,
+}
+
+impl Memory {
+    pub fn new(size: usize, out_of_bounds: OutOfBounds) -> Self {
+        Self {
+            bytes: vec![0; size],
+            out_of_bounds,
+            read_only: None,
+            fault: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn resize(&mut self, size: usize) {
+        self.bytes.resize(size, 0);
+    }
+
+    pub fn set_out_of_bounds(&mut self, out_of_bounds: OutOfBounds) {
+        self.out_of_bounds = out_of_bounds;
+    }
+
+    // Writes into this range are dropped (or fault, if out of bounds accesses fault)
+    pub fn set_read_only(&mut self, read_only: Option<Range<usize>>) {
+        self.read_only = read_only;
+    }
+
+    pub fn read(&mut self, address: usize) -> u8 {
+        match self.resolve(address, false) {
+            Some(address) => self.bytes[address],
+            None => 0,
+        }
+    }
+
+    // Returns the address actually written to, after wrapping/clamping
+    pub fn write(&mut self, address: usize, value: u8) -> Option<usize> {
+        let address = self.resolve(address, true)?;
+
+        if let Some(read_only) = &self.read_only {
+            if read_only.contains(&address) {
+                if self.out_of_bounds == OutOfBounds::Fault {
+                    self.record_fault(address, true);
+                }
+                return None;
+            }
+        }
+
+        self.bytes[address] = value;
+        Some(address)
+    }
+
+    // Direct access for the emulator itself (ROM/font loading, display mirroring)
+    // Bypasses the read only region and bounds policy
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    pub fn take_fault(&mut self) -> Option<MemoryFault> {
+        self.fault.take()
+    }
+
+    fn resolve(&mut self, address: usize, write: bool) -> Option<usize> {
+        if address < self.bytes.len() {
+            return Some(address);
+        }
+
+        match self.out_of_bounds {
+            OutOfBounds::Wrap => Some(address % self.bytes.len()),
+            OutOfBounds::Clamp => Some(self.bytes.len() - 1),
+            OutOfBounds::Fault => {
+                self.record_fault(address, write);
+                None
+            }
+        }
+    }
+
+    // Keep the first fault, that's the one that matters
+    fn record_fault(&mut self, address: usize, write: bool) {
+        if self.fault.is_none() {
+            self.fault = Some(MemoryFault { address, write });
+        }
+    }
+}
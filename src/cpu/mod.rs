@@ -1,62 +1,59 @@
 #![allow(non_snake_case)]
 
-use std::{fs::File, io::Read};
+use std::{collections::HashMap, fs, ops::Range};
 
+mod color;
 mod config;
-
-use crate::{MAX_RESOLUTION_HEIGHT, MAX_RESOLUTION_WIDTH};
+mod debug;
+mod decode_cache;
+mod extension;
+mod fault;
+mod font;
+mod framebuffer;
+mod instruction;
+#[cfg(feature = "jit")]
+mod jit;
+mod memory;
+mod observer;
+mod recompiler;
+#[cfg(test)]
+mod tests;
 
 use self::config::CPUConfig;
 
 // Export from CPU module
-pub use config::CPUVariant;
-
-const FONT_LOCATION: usize = 0x0;
-const FONT_BYTES: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
-// Fonts from Octo
-const BIG_FONT_LOCATION: usize = FONT_LOCATION + FONT_BYTES.len();
-const BIG_FONT_BYTES: [u8; 160] = [
-    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
-    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
-    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
-    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
-    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
-    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
-    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
-    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
-    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
-    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
-    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
-    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
-];
+pub use color::ColorMap;
+pub use config::{CPUVariant, QuirkOverrides, LOAD_STORE_LOOKAHEAD};
+pub use debug::{AssertFailure, Breakpoint, DebugAction};
+pub use extension::{Extension, Machine};
+pub use fault::Fault;
+pub use font::{Font, FontSet};
+pub use framebuffer::Framebuffer;
+pub use instruction::Instruction;
+pub use memory::{
+    MemoryFault, OutOfBounds, MEMORY_SIZE_CHIP8, MEMORY_SIZE_MEGACHIP, MEMORY_SIZE_XO_CHIP,
+};
+pub use observer::{Draw, Observer, Timer};
+pub use recompiler::RecompiledBlock;
+
+use self::{
+    decode_cache::{DecodeCache, Handler, Operands},
+    framebuffer::ROW_WORDS,
+    memory::Memory,
+};
 
 enum PollingKeyPress {
-    Polling(usize),
+    Polling(usize, u16), // Register to store the key in, keys pressed since polling began
     NotPolling,
 }
 
+// Where an opcode goes on this variant
+enum Route {
+    Builtin(Handler),
+    Sys,       // 0NNN, a debug hook or an extension decides when it runs
+    Extension, // Anything the variant doesn't handle itself
+}
+
 enum CondCheck {
     NN(u8),
     VY(usize),
@@ -65,37 +62,56 @@ enum CondCheck {
 // Entry point to chip8 emulator
 pub struct CPU {
     pub running: bool,
-    config: CPUConfig, // Config, for quirks/variant
-    pub pixels: [[bool; MAX_RESOLUTION_WIDTH]; MAX_RESOLUTION_HEIGHT], // Pixel memory
-    memory: [u8; 4096], // RAM
-    V: [u8; 0x10],     // V registers
-    I: usize,          // 12-bit index reg
-    pc: usize,         // Program counter
-    delay_timer: u8,   // Delay timer
-    sound_timer: u8,   // Sound timer
-    stack: [usize; 16], // Stack for return addr
-    sp: usize,         // Stack pointer
-    keys: u16,         // Keys pressed
+    config: CPUConfig,                  // Config, for quirks/variant
+    framebuffer: Framebuffer,           // Pixel memory
+    memory: Memory,                     // RAM
+    V: [u8; 0x10],                      // V registers
+    I: usize,                           // 12-bit index reg
+    pc: usize,                          // Program counter
+    delay_timer: u8,                    // Delay timer
+    sound_timer: u8,                    // Sound timer
+    stack: [usize; 16],                 // Stack for return addr
+    sp: usize,                          // Stack pointer
+    keys: u32,                          // Keys pressed, second keypad (CHIP-8X) in the upper half
     polling_key_press: PollingKeyPress, // Check polling
-    vblank: bool,      // Vertical blanking
+    vblank: bool,                       // Vertical blanking
 
     curr_res: (usize, usize),
     pub max_res: (usize, usize),
 
     flag_registers: [u8; 0x10],
+
+    font_location: usize,     // Start of small font glyphs
+    big_font_location: usize, // Start of big font glyphs, right after the small font
+    font_end: usize,
+
+    timer_wait: bool,  // Waiting for the delay timer to reach 0 (CHIP-8E)
+    port_output: u8,   // Last value written to the I/O port
+    port_input: u8,    // Last value latched on the I/O port
+    port_strobe: bool, // Input has been strobed since it was last read
+
+    color_map: ColorMap, // CHIP-8X colour zones
+
+    fault: Option<Fault>, // Set when the CPU stopped on a bad memory access or stack
+
+    opcode: u16, // Instruction being executed, for observers
+    observers: Vec<Box<dyn Observer>>,
+
+    decode_cache: DecodeCache,
+    extensions: Vec<Box<dyn Extension>>, // Custom opcodes, checked for anything the variant doesn't handle
+    debug_hooks: HashMap<usize, DebugAction>, // 0NNN addresses that trigger debug actions
+    paused: bool,
+    assert_failure: Option<AssertFailure>,
+    breakpoint: Option<Breakpoint>, // Break hook hit since the frontend last checked
 }
 
 impl CPU {
     pub fn new(variant: CPUVariant) -> Self {
-        let mut memory = [0; 4096];
-        for (i, &font_byte) in FONT_BYTES.iter().enumerate() {
-            memory[FONT_LOCATION + i] = font_byte;
-        }
-
-        for (i, &big_font_byte) in BIG_FONT_BYTES.iter().enumerate() {
-            memory[BIG_FONT_LOCATION + i] = big_font_byte;
-        }
+        Self::with_extensions(variant, Vec::new())
+    }
 
+    // Extensions are asked in order, the first one to claim an opcode runs it
+    pub fn with_extensions(variant: CPUVariant, extensions: Vec<Box<dyn Extension>>) -> Self {
         let config = CPUConfig::from(variant);
 
         // Pull starting PC from config
@@ -104,10 +120,19 @@ impl CPU {
         let curr_res = config.resolutions[0];
         let max_res = *config.resolutions.last().unwrap();
 
-        Self {
+        let font = Font::from(config.font);
+        let font_location = config.font_location;
+
+        let config_memory_size = config.memory_size;
+        let mut memory = Memory::new(config.memory_size, config.out_of_bounds);
+        if config.read_only_interpreter {
+            memory.set_read_only(Some(0..config.pc_start));
+        }
+
+        let mut cpu = Self {
             running: true,
             config,
-            pixels: [[false; MAX_RESOLUTION_WIDTH]; MAX_RESOLUTION_HEIGHT],
+            framebuffer: Framebuffer::new(max_res.0, max_res.1),
             memory,
             V: [0; 0x10],
             I: 0,
@@ -122,25 +147,166 @@ impl CPU {
             max_res,
             vblank: false,
             flag_registers: [0; 0x10],
+            font_location: 0,
+            big_font_location: 0,
+            font_end: 0,
+            timer_wait: false,
+            port_output: 0,
+            port_input: 0,
+            port_strobe: false,
+            color_map: ColorMap::default(),
+            fault: None,
+            opcode: 0,
+            observers: Vec::new(),
+            decode_cache: DecodeCache::new(config_memory_size),
+            extensions,
+            debug_hooks: HashMap::new(),
+            paused: false,
+            assert_failure: None,
+            breakpoint: None,
+        };
+
+        // Built-in fonts always fit
+        cpu.load_font(&font, font_location).unwrap();
+        cpu
+    }
+
+    pub fn font_set(&self) -> FontSet {
+        self.config.font
+    }
+
+    // Replace the font, should be called before loading the ROM
+    pub fn load_font(
+        &mut self,
+        font: &Font,
+        location: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let end = location + font.len();
+        if end > self.memory.len() {
+            return Err(format!("font at {:#X} doesn't fit in memory", location).into());
         }
+
+        let memory = self.memory.bytes_mut();
+        memory[location..location + font.small.len()].copy_from_slice(&font.small);
+        memory[location + font.small.len()..end].copy_from_slice(&font.big);
+
+        self.decode_cache.clear();
+
+        self.font_location = location;
+        self.big_font_location = location + font.small.len();
+        self.font_end = end;
+        Ok(())
+    }
+
+    pub fn apply_quirks(&mut self, quirks: &QuirkOverrides) {
+        self.config.apply_overrides(quirks);
+
+        // Memory layout can change too, existing contents (fonts) are kept
+        self.memory.resize(self.config.memory_size);
+        self.decode_cache.resize(self.config.memory_size);
+        self.memory.set_out_of_bounds(self.config.out_of_bounds);
+        self.memory
+            .set_read_only(match self.config.read_only_interpreter {
+                true => Some(0..self.config.pc_start),
+                false => None,
+            });
+    }
+
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    // Developer mode, without hooks every 0NNN is ignored as usual
+    pub fn set_debug_hook(&mut self, address: usize, action: DebugAction) {
+        self.debug_hooks.insert(address & 0xFFF, action);
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn assert_failure(&self) -> Option<AssertFailure> {
+        self.assert_failure
+    }
+
+    pub fn take_breakpoint(&mut self) -> Option<Breakpoint> {
+        self.breakpoint.take()
+    }
+
+    // On by default, turning it off decodes every instruction as it's fetched
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache.set_enabled(enabled);
+    }
+
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    // Read-only views of CPU state, for observers and debuggers
+    pub fn registers(&self) -> &[u8; 0x10] {
+        &self.V
+    }
+
+    pub fn index(&self) -> usize {
+        self.I
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    pub fn keys(&self) -> u32 {
+        self.keys
+    }
+
+    pub fn font_range(&self) -> Range<usize> {
+        self.font_location..self.font_end
+    }
+
+    // Reads bypass the bus, so peeking never faults or shows up in observers
+    pub fn peek_memory(&self, address: usize) -> Option<u8> {
+        self.memory.bytes().get(address).copied()
     }
 
     pub fn load_rom(&mut self, rom: String) {
-        let mut file = File::open(rom).unwrap();
+        let rom = fs::read(rom).unwrap();
+        self.load_rom_bytes(&rom);
+    }
 
-        let res = file.read(&mut self.memory[self.config.pc_start..]);
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) {
+        // Truncate anything that doesn't fit in memory
+        let memory = &mut self.memory.bytes_mut()[self.config.pc_start..];
+        let len = rom.len().min(memory.len());
+        memory[..len].copy_from_slice(&rom[..len]);
 
-        // Do this to pacify clippy
-        if res.is_ok() {}
+        self.decode_cache.clear();
     }
 
     pub fn decrement_timers(&mut self) {
+        self.notify(|observer| observer.on_frame());
+
         if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+            self.set_timer(Timer::Delay, self.delay_timer - 1);
         }
 
         if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+            self.set_timer(Timer::Sound, self.sound_timer - 1);
         }
     }
 
@@ -148,16 +314,77 @@ impl CPU {
         self.sound_timer > 0
     }
 
+    // Keys 0x10-0x1F are the second keypad
     pub fn press_key(&mut self, key_index: u8) {
-        self.keys |= 1u16 << key_index;
+        match self.config.single_key_keypad {
+            true => self.keys = 1u32 << key_index,
+            false => self.keys |= 1u32 << key_index,
+        }
+
+        // FX0A only reads the first keypad
+        if key_index > 0xF {
+            return;
+        }
+
+        if let PollingKeyPress::Polling(x, pressed) = self.polling_key_press {
+            match self.config.key_wait_on_press {
+                true => {
+                    self.set_v(x, key_index);
+                    self.polling_key_press = PollingKeyPress::NotPolling;
+                }
+                false => {
+                    self.polling_key_press = PollingKeyPress::Polling(x, pressed | 1 << key_index)
+                }
+            }
+        }
     }
 
     pub fn release_key(&mut self, key_index: u8) {
-        self.keys &= !(1u16 << key_index);
+        self.keys &= !(1u32 << key_index);
+
+        // FX0A only reads the first keypad
+        if key_index > 0xF {
+            return;
+        }
+
+        // Keys held down before FX0A started don't count
+        if let PollingKeyPress::Polling(x, pressed) = self.polling_key_press {
+            if pressed & (1 << key_index) != 0 {
+                self.set_v(x, key_index);
+                self.polling_key_press = PollingKeyPress::NotPolling;
+            }
+        }
+    }
+
+    // Latch a value on the I/O port, as if a peripheral strobed it in
+    pub fn write_port(&mut self, value: u8) {
+        self.port_input = value;
+        self.port_strobe = true;
+    }
+
+    pub fn read_port(&self) -> u8 {
+        self.port_output
+    }
+
+    // Only available on variants with colour support
+    // What to draw, is_dirty() says whether it changed since mark_displayed()
+    pub fn display(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
 
-        if let PollingKeyPress::Polling(x) = self.polling_key_press {
-            self.V[x] = key_index;
-            self.polling_key_press = PollingKeyPress::NotPolling;
+    pub fn mark_displayed(&mut self) {
+        self.framebuffer.set_dirty(false);
+    }
+
+    // For frontends whose output changed without the screen changing, e.g. a new palette
+    pub fn request_redraw(&mut self) {
+        self.framebuffer.set_dirty(true);
+    }
+
+    pub fn color_map(&self) -> Option<&ColorMap> {
+        match self.config.color_enabled {
+            true => Some(&self.color_map),
+            false => None,
         }
     }
 
@@ -169,86 +396,398 @@ impl CPU {
         self.vblank = false;
     }
 
-    pub fn process(&mut self) {
-        if !self.running {
-            return;
+    // Calls process() up to cycles times, compiled blocks are used when the JIT is on
+    // Stops early at vblank or a pause, so a frame can be run in one call
+    pub fn run(&mut self, cycles: usize) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            // Blocks only touch registers, they can't wait for vblank or pause
+            #[cfg(feature = "jit")]
+            if let Some(len) = self.run_block(remaining) {
+                remaining -= len;
+                continue;
+            }
+
+            self.process();
+            remaining -= 1;
+
+            if self.vblank || self.paused {
+                break;
+            }
+        }
+    }
+
+    // Compiles hot blocks of register-only code to native code, used by run()
+    // Observers turn it off while attached, they expect to see every instruction
+    #[cfg(feature = "jit")]
+    pub fn enable_jit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.decode_cache
+            .set_jit(Some(jit::Jit::new(self.config.memory_size)?));
+        Ok(())
+    }
+
+    // Instructions run, or None if the interpreter should take this one
+    #[cfg(feature = "jit")]
+    fn run_block(&mut self, budget: usize) -> Option<usize> {
+        if !self.observers.is_empty() || !self.ready() {
+            return None;
+        }
+
+        let block = self
+            .decode_cache
+            .block(self.pc, self.memory.bytes(), &self.config)?;
+        // Blocks run to the end, so one that doesn't fit would overrun the frame
+        if block.len > budget {
+            return None;
         }
 
-        if let PollingKeyPress::Polling(_) = self.polling_key_press {
+        self.pc = block.run(&mut self.V, &mut self.I);
+        self.opcode = block.last_opcode;
+        Some(block.len)
+    }
+
+    // Same as run(), with blocks from CPU::recompile in place of the interpreter where possible
+    // A block only runs while memory still holds the code it was built from
+    pub fn run_recompiled(
+        &mut self,
+        cycles: usize,
+        blocks: fn(usize) -> Option<&'static RecompiledBlock>,
+    ) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            match blocks(self.pc) {
+                Some(block)
+                    if block.len <= remaining
+                        && self.observers.is_empty()
+                        && self
+                            .memory
+                            .bytes()
+                            .get(block.start..block.start + block.code.len())
+                            == Some(block.code)
+                        && self.ready() =>
+                {
+                    self.pc = (block.run)(&mut self.V, &mut self.I);
+                    self.opcode = block.last_opcode;
+                    remaining -= block.len;
+                }
+                _ => {
+                    self.process();
+                    remaining -= 1;
+
+                    if self.vblank || self.paused {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether the next instruction can run, or the CPU is stopped or waiting
+    fn ready(&mut self) -> bool {
+        if !self.running || self.paused {
+            return false;
+        }
+
+        if let PollingKeyPress::Polling(..) = self.polling_key_press {
+            return false;
+        }
+
+        if self.timer_wait {
+            if self.delay_timer > 0 {
+                return false;
+            }
+            self.timer_wait = false;
+        }
+        true
+    }
+
+    pub fn process(&mut self) {
+        if !self.ready() {
             return;
         }
 
-        let upper = self.memory[self.pc];
-        let lower = self.memory[self.pc + 1];
-        let instruction = ((upper as u16) << 8) | (lower as u16);
+        let pc = self.pc;
+        let (handler, operands) = match self.decode_cache.get(pc) {
+            Some(entry) => entry,
+            None => {
+                let upper = self.memory.read(pc);
+                let lower = self.memory.read(pc + 1);
+                let operands = Operands::new(((upper as u16) << 8) | (lower as u16));
+
+                let handler = self.handler(operands);
+                self.decode_cache.insert(pc, handler, operands);
+                (handler, operands)
+            }
+        };
 
-        let (b1, b2) = (upper >> 4, upper & 0xF);
-        let (b3, b4) = (lower >> 4, lower & 0xF);
+        let instruction = operands.opcode;
+        self.opcode = instruction;
+        self.notify(|observer| observer.on_fetch(pc, instruction));
 
         // Increment here, so that jumps aren't affected
         self.pc += 2;
 
-        // Helper vars
-        let nnn = (instruction & 0xFFF) as usize;
-        let nn = lower;
-        let x = b2 as usize;
-        let y = b3 as usize;
-        let n = b4 as usize;
+        handler(self, operands);
 
-        match b1 {
+        // A faulting access stops the CPU on the offending instruction
+        if let Some(fault) = self.memory.take_fault() {
+            self.stop_on(Fault::Memory(fault));
+        }
+
+        // Observers get to see the whole CPU, so move them out while they look
+        if !self.observers.is_empty() {
+            let mut observers = std::mem::take(&mut self.observers);
+            for observer in &mut observers {
+                observer.on_execute(self, pc, instruction);
+            }
+            self.observers = observers;
+        }
+    }
+
+    // Picks what an opcode does on this variant, the result is cached per address
+    fn handler(&self, operands: Operands) -> Handler {
+        match self.route(operands) {
+            Route::Builtin(handler) => handler,
+            Route::Sys => |cpu, op| cpu.sys(op.nnn()),
+            Route::Extension => |cpu, op| cpu.execute_extension(op.opcode),
+        }
+    }
+
+    fn route(&self, operands: Operands) -> Route {
+        let instruction = operands.opcode;
+        let (b1, b2) = ((instruction >> 12) as u8, operands.x() as u8);
+        let (b3, b4) = (operands.y() as u8, operands.n() as u8);
+        let lower = operands.nn();
+
+        let handler: Handler = match b1 {
             0 => match lower {
-                _ if b3 == 0xC && self.config.scrolling_enabled => self.scroll_down(n), // 00CN
-                0xE0 => self.clear_screen(),
-                0xEE => self.return_subr(),
-                0xFB if self.config.scrolling_enabled => self.scroll_right(),
-                0xFC if self.config.scrolling_enabled => self.scroll_left(),
-                0xFD if self.config.hires_enabled => self.halt(),
-                0xFE if self.config.hires_enabled => self.set_hires(false),
-                0xFF if self.config.hires_enabled => self.set_hires(true),
-                _ => self.sys(nnn),
+                0x51 if b2 == 1 && self.config.chip8e_enabled => |cpu, _| cpu.timer_wait = true, // 0151
+                0x88 if b2 == 1 && self.config.chip8e_enabled => |cpu, _| cpu.pc += 2, // 0188
+                0xED if b2 == 0 && self.config.chip8e_enabled => |cpu, _| cpu.halt(),  // 00ED
+                0xA0 if b2 == 2 && self.config.color_enabled => |cpu, _| {
+                    cpu.color_map.cycle_background();
+                    cpu.framebuffer.set_dirty(true);
+                }, // 02A0
+                _ if b3 == 0xC && self.config.scrolling_enabled => {
+                    |cpu, op| cpu.scroll_down(op.n())
+                } // 00CN
+                0xE0 => |cpu, _| cpu.clear_screen(),
+                0xEE => |cpu, _| cpu.return_subr(),
+                0xFB if self.config.scrolling_enabled => |cpu, _| cpu.scroll_right(),
+                0xFC if self.config.scrolling_enabled => |cpu, _| cpu.scroll_left(),
+                0xFD if self.config.hires_enabled => |cpu, _| cpu.halt(),
+                0xFE if self.config.hires_enabled => |cpu, _| cpu.set_hires(false),
+                0xFF if self.config.hires_enabled => |cpu, _| cpu.set_hires(true),
+                _ => return Route::Sys,
             },
-            1 => self.jmp(nnn),
-            2 => self.call(nnn),
-            3 => self.cond_check(x, CondCheck::NN(nn), true),
-            4 => self.cond_check(x, CondCheck::NN(nn), false),
+            1 => |cpu, op| cpu.jmp(op.nnn()),
+            2 => |cpu, op| cpu.call(op.nnn()),
+            3 => |cpu, op| cpu.cond_check(op.x(), CondCheck::NN(op.nn()), true),
+            4 => |cpu, op| cpu.cond_check(op.x(), CondCheck::NN(op.nn()), false),
             5 => match b4 {
-                0 => self.cond_check(x, CondCheck::VY(y), true),
-                _ => unreachable!(),
+                0 => |cpu, op| cpu.cond_check(op.x(), CondCheck::VY(op.y()), true),
+                1 if self.config.chip8e_enabled => |cpu, op| cpu.skip_greater(op.x(), op.y()),
+                1 if self.config.color_enabled => |cpu, op| cpu.add_nibbles(op.x(), op.y()),
+                2 if self.config.chip8e_enabled => |cpu, op| cpu.save_range(op.x(), op.y()),
+                3 if self.config.chip8e_enabled => |cpu, op| cpu.load_range(op.x(), op.y()),
+                _ => return Route::Extension,
+            },
+            6 => |cpu, op| cpu.set_immediate(op.x(), op.nn()),
+            7 => |cpu, op| cpu.add_immediate(op.x(), op.nn()),
+            8 => |cpu, op| cpu.execute_arithmetic(op.x(), op.y(), op.n() as u8),
+            9 => match b4 {
+                0 => |cpu, op| cpu.cond_check(op.x(), CondCheck::VY(op.y()), false),
+                _ => return Route::Extension,
             },
-            6 => self.set_immediate(x, nn),
-            7 => self.add_immediate(x, nn),
-            8 => self.execute_arithmetic(x, y, b4),
-            9 => self.cond_check(x, CondCheck::VY(y), false),
-            0xA => self.set_i(nnn),
-            0xB => self.jmp_relative(nnn),
-            0xC => self.set_random(x, nn),
-            0xD => self.draw(x, y, n),
+            0xA => |cpu, op| cpu.set_i(op.nnn()),
+            0xB => match b2 {
+                0xB if self.config.chip8e_enabled => |cpu, op| cpu.pc -= op.nn() as usize, // BBNN
+                0xF if self.config.chip8e_enabled => |cpu, op| cpu.pc += op.nn() as usize, // BFNN
+                _ if self.config.color_enabled => |cpu, op| cpu.set_color(op.x(), op.y(), op.n()),
+                _ => |cpu, op| cpu.jmp_relative(op.nnn()),
+            },
+            0xC => |cpu, op| cpu.set_random(op.x(), op.nn()),
+            0xD => |cpu, op| cpu.draw(op.x(), op.y(), op.n()),
             0xE => match lower {
-                0x9E => self.key_check(x, true),
-                0xA1 => self.key_check(x, false),
-                _ => unreachable!(),
+                0x9E => |cpu, op| cpu.key_check(op.x(), 0, true),
+                0xA1 => |cpu, op| cpu.key_check(op.x(), 0, false),
+                0xF2 if self.config.color_enabled => |cpu, op| cpu.key_check(op.x(), 1, true),
+                0xF5 if self.config.color_enabled => |cpu, op| cpu.key_check(op.x(), 1, false),
+                _ => return Route::Extension,
             },
             0xF => match lower {
-                0x07 => self.set_immediate(x, self.delay_timer),
-                0x0A => self.get_key(x),
-                0x15 => self.set_delay(x),
-                0x18 => self.set_sound(x),
-                0x1E => self.add_i(x),
-                0x29 => self.set_i_sprite(x),
-                0x30 => self.set_i_big_sprite(x),
-                0x33 => self.set_bcd(x),
-                0x55 => self.reg_dump(x),
-                0x65 => self.reg_load(x),
-                0x75 if self.config.flag_registers_enabled => self.flag_dump(x),
-                0x85 if self.config.flag_registers_enabled => self.flag_load(x),
-                _ => unreachable!(),
+                0x03 if self.config.io_port_enabled => |cpu, op| cpu.port_output = cpu.V[op.x()],
+                0x07 => |cpu, op| cpu.set_immediate(op.x(), cpu.delay_timer),
+                0x0A => |cpu, op| cpu.get_key(op.x()),
+                0x15 => |cpu, op| cpu.set_delay(op.x()),
+                0x18 => |cpu, op| cpu.set_sound(op.x()),
+                0x1B if self.config.chip8e_enabled => |cpu, op| cpu.pc += cpu.V[op.x()] as usize,
+                0x1E => |cpu, op| cpu.add_i(op.x()),
+                0x29 => |cpu, op| cpu.set_i_sprite(op.x()),
+                0x30 => |cpu, op| cpu.set_i_big_sprite(op.x()),
+                0x33 => |cpu, op| cpu.set_bcd(op.x()),
+                0x4F if self.config.chip8e_enabled => |cpu, op| {
+                    cpu.set_delay(op.x());
+                    cpu.timer_wait = true;
+                },
+                0x55 => |cpu, op| cpu.reg_dump(op.x()),
+                0x65 => |cpu, op| cpu.reg_load(op.x()),
+                0x75 if self.config.flag_registers_enabled => |cpu, op| cpu.flag_dump(op.x()),
+                0x85 if self.config.flag_registers_enabled => |cpu, op| cpu.flag_load(op.x()),
+                0xE3 if self.config.io_port_enabled => |cpu, op| cpu.port_read(op.x(), true),
+                0xE7 if self.config.io_port_enabled => |cpu, op| cpu.port_read(op.x(), false),
+                0xF8 if self.config.color_enabled => |cpu, op| cpu.port_output = cpu.V[op.x()],
+                0xFB if self.config.color_enabled => |cpu, op| cpu.port_read(op.x(), true),
+                _ => return Route::Extension,
             },
-            _ => unreachable!(),
+            _ => return Route::Extension,
+        };
+        Route::Builtin(handler)
+    }
+
+    fn notify(&mut self, mut event: impl FnMut(&mut dyn Observer)) {
+        for observer in &mut self.observers {
+            event(observer.as_mut());
+        }
+    }
+
+    // Everything the ROM does to registers, memory and timers goes through these
+    fn set_v(&mut self, x: usize, value: u8) {
+        self.V[x] = value;
+
+        let opcode = self.opcode;
+        self.notify(|observer| observer.on_register_write(x, value, opcode));
+    }
+
+    fn read_memory(&mut self, address: usize) -> u8 {
+        let value = self.memory.read(address);
+
+        let opcode = self.opcode;
+        self.notify(|observer| observer.on_memory_read(address, value, opcode));
+        value
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) {
+        if let Some(address) = self.memory.write(address, value) {
+            self.decode_cache.invalidate(address);
         }
+
+        let opcode = self.opcode;
+        self.notify(|observer| observer.on_memory_write(address, value, opcode));
     }
 
-    fn sys(&mut self, _nnn: usize) {
-        // This is a noop
+    fn set_timer(&mut self, timer: Timer, value: u8) {
+        match timer {
+            Timer::Delay => self.delay_timer = value,
+            Timer::Sound => self.sound_timer = value,
+        }
+
+        self.notify(|observer| observer.on_timer(timer, value));
+    }
+
+    fn sys(&mut self, nnn: usize) {
+        // This is a noop, unless it's a debug hook or an extension wants it
+        let Some(&action) = self.debug_hooks.get(&nnn) else {
+            if self.extension_index(self.opcode).is_some() {
+                self.execute_extension(self.opcode);
+            }
+            return;
+        };
+
+        let address = self.pc - 2;
+        match action {
+            DebugAction::Break => {
+                self.breakpoint = Some(Breakpoint {
+                    address,
+                    registers: self.debug_registers(),
+                });
+                self.paused = true;
+            }
+            DebugAction::PrintRegisters => println!("{:#05X}: {}", address, self.debug_registers()),
+            DebugAction::PrintString => {
+                println!(
+                    "{}",
+                    debug::format_string(self.memory.bytes(), &self.V, self.I)
+                )
+            }
+            DebugAction::Assert => {
+                let register = self.peek_memory(self.I).unwrap_or(0) as usize & 0xF;
+                let expected = self.peek_memory(self.I + 1).unwrap_or(0);
+                if self.V[register] != expected {
+                    self.assert_failure = Some(AssertFailure {
+                        address,
+                        register,
+                        expected,
+                        actual: self.V[register],
+                    });
+                    self.running = false;
+                }
+            }
+        }
+    }
+
+    fn extension_index(&self, opcode: u16) -> Option<usize> {
+        self.extensions
+            .iter()
+            .position(|extension| extension.claims(opcode))
+    }
+
+    fn execute_extension(&mut self, opcode: u16) {
+        let Some(index) = self.extension_index(opcode) else {
+            unreachable!("unknown opcode {:#06X}", opcode);
+        };
+
+        let mut machine = Machine {
+            V: &mut self.V,
+            I: &mut self.I,
+            pc: &mut self.pc,
+            memory: &mut self.memory,
+            decode_cache: &mut self.decode_cache,
+            framebuffer: &mut self.framebuffer,
+            resolution: self.max_res,
+            opcode,
+            observers: &mut self.observers,
+            written: None,
+            drawn: false,
+        };
+        self.extensions[index].execute(opcode, &mut machine);
+
+        let (written, drawn) = (machine.written, machine.drawn);
+        if let Some(written) = written {
+            self.sync_memory_to_display(written.start, written.len());
+        }
+        if drawn {
+            self.sync_display_to_memory();
+        }
+    }
+
+    // Instruction::decode, with opcodes this variant leaves to extensions decoded as Custom
+    // (or Unknown, when no extension claims them)
+    pub fn decode(&self, opcode: u16) -> Instruction {
+        let claimed = self.extension_index(opcode).is_some();
+        match self.route(Operands::new(opcode)) {
+            Route::Builtin(_) => Instruction::decode(opcode),
+            // Debug hooks come first, see sys()
+            Route::Sys if claimed && !self.debug_hooks.contains_key(&(opcode as usize & 0xFFF)) => {
+                Instruction::Custom(opcode)
+            }
+            Route::Sys => Instruction::Sys(opcode & 0xFFF),
+            Route::Extension if claimed => Instruction::Custom(opcode),
+            Route::Extension => Instruction::Unknown(opcode),
+        }
+    }
+
+    pub fn disassemble(&self, opcode: u16) -> String {
+        match self.decode(opcode) {
+            Instruction::Custom(_) => self.extensions[self.extension_index(opcode).unwrap()]
+                .disassemble(opcode)
+                .unwrap_or_else(|| format!("{:#06X}", opcode)),
+            instruction => instruction.to_string(),
+        }
+    }
+
+    fn debug_registers(&self) -> String {
+        debug::format_registers(&self.V, self.I, self.pc, self.stack())
     }
 
     fn halt(&mut self) {
@@ -256,8 +795,52 @@ impl CPU {
     }
 
     fn clear_screen(&mut self) {
-        for row in &mut self.pixels {
-            row.fill(false);
+        self.framebuffer.clear();
+
+        self.sync_display_to_memory();
+    }
+
+    // Memory mapped display (DREAM 6800), one bit per pixel
+    fn display_bytes(&self) -> Option<(usize, usize)> {
+        self.config
+            .display_location
+            .map(|location| (location, self.max_res.0 * self.max_res.1 / 8))
+    }
+
+    fn sync_display_to_memory(&mut self) {
+        let Some((location, _)) = self.display_bytes() else {
+            return;
+        };
+
+        let bytes_per_row = self.max_res.0 / 8;
+        for y in 0..self.max_res.1 {
+            for x_byte in 0..bytes_per_row {
+                let byte = (0..8).fold(0u8, |byte, bit| {
+                    (byte << 1) | self.framebuffer.pixel(x_byte * 8 + bit, y) as u8
+                });
+                self.memory.bytes_mut()[location + y * bytes_per_row + x_byte] = byte;
+            }
+        }
+        self.decode_cache
+            .invalidate_range(location, bytes_per_row * self.max_res.1);
+    }
+
+    // Called after the ROM writes to memory, picks up any writes into display memory
+    fn sync_memory_to_display(&mut self, start: usize, len: usize) {
+        let Some((location, size)) = self.display_bytes() else {
+            return;
+        };
+        if start >= location + size || start + len <= location {
+            return;
+        }
+
+        let bytes_per_row = self.max_res.0 / 8;
+        for y in 0..self.max_res.1 {
+            for x in 0..self.max_res.0 {
+                let byte = self.memory.bytes()[location + y * bytes_per_row + x / 8];
+                self.framebuffer
+                    .set_pixel(x, y, byte & (0x80 >> (x % 8)) != 0);
+            }
         }
     }
 
@@ -267,16 +850,7 @@ impl CPU {
             true => self.max_res.1 / self.curr_res.1,
             false => 1,
         };
-
-        for r in (0..self.max_res.1).rev() {
-            match r >= n {
-                true => {
-                    let slice = self.pixels[r - n];
-                    self.pixels[r].clone_from_slice(&slice);
-                }
-                false => self.pixels[r].fill(false),
-            }
-        }
+        self.framebuffer.scroll_down(n);
     }
 
     fn scroll_right(&mut self) {
@@ -284,15 +858,7 @@ impl CPU {
             true => self.max_res.1 / self.curr_res.1,
             false => 1,
         };
-
-        for row in &mut self.pixels {
-            for p in (0..self.max_res.0).rev() {
-                match p < scroll_amount {
-                    true => row[p] = false,
-                    false => row[p] = row[p - scroll_amount],
-                }
-            }
-        }
+        self.framebuffer.scroll_right(scroll_amount);
     }
 
     fn scroll_left(&mut self) {
@@ -300,26 +866,43 @@ impl CPU {
             true => self.max_res.1 / self.curr_res.1,
             false => 1,
         };
+        self.framebuffer.scroll_left(scroll_amount);
+    }
 
-        for row in &mut self.pixels {
-            for p in 0..self.max_res.0 {
-                match p >= self.max_res.0 - scroll_amount {
-                    true => row[p] = false,
-                    false => row[p] = row[p + scroll_amount],
-                }
-            }
-        }
+    fn stop_on(&mut self, fault: Fault) {
+        self.fault = Some(fault);
+        self.running = false;
     }
 
     fn call(&mut self, nnn: usize) {
+        let from = self.pc - 2;
+
+        // Stop rather than trample memory
+        if self.sp == self.stack.len() {
+            self.stop_on(Fault::StackOverflow { address: from });
+            return;
+        }
+
+        self.notify(|observer| observer.on_call(from, nnn));
+
         self.stack[self.sp] = self.pc;
         self.sp += 1;
         self.pc = nnn;
     }
     fn return_subr(&mut self) {
+        let from = self.pc - 2;
+
+        if self.sp == 0 {
+            self.stop_on(Fault::StackUnderflow { address: from });
+            return;
+        }
+
         // Decrement SP first to get back the original return PC
         self.sp -= 1;
         self.pc = self.stack[self.sp];
+
+        let to = self.pc;
+        self.notify(|observer| observer.on_return(from, to));
     }
 
     // Hires
@@ -329,11 +912,20 @@ impl CPU {
             false => *self.config.resolutions.first().unwrap(),
         };
 
-        // TODO: Clear screen for now
-        self.clear_screen();
+        if self.config.hires_clear_quirk {
+            self.clear_screen();
+        }
     }
 
     fn jmp(&mut self, nnn: usize) {
+        // Hires ROMs start with "1260", which the patched interpreter treats as a mode switch
+        if let Some(entry) = self.config.hires_entry {
+            if nnn == 0x260 && self.pc - 2 == self.config.pc_start {
+                self.pc = entry;
+                return;
+            }
+        }
+
         self.pc = nnn;
     }
     fn jmp_relative(&mut self, nnn: usize) {
@@ -358,90 +950,119 @@ impl CPU {
         }
     }
 
+    fn skip_greater(&mut self, x: usize, y: usize) {
+        if self.V[x] > self.V[y] {
+            self.pc += 2;
+        }
+    }
+
+    // CHIP-8X 5XY1, each nibble is added separately and wraps at 8
+    fn add_nibbles(&mut self, x: usize, y: usize) {
+        let (vx, vy) = (self.V[x], self.V[y]);
+        let high = ((vx >> 4) + (vy >> 4)) & 0x7;
+        let low = ((vx & 0xF) + (vy & 0xF)) & 0x7;
+        self.set_v(x, (high << 4) | low);
+    }
+
     fn set_immediate(&mut self, x: usize, nn: u8) {
-        self.V[x] = nn;
+        self.set_v(x, nn);
     }
     fn add_immediate(&mut self, x: usize, nn: u8) {
-        self.V[x] = self.V[x].overflowing_add(nn).0;
+        self.set_v(x, self.V[x].overflowing_add(nn).0);
     }
 
     fn execute_arithmetic(&mut self, x: usize, y: usize, code: u8) {
         match code {
-            0 => self.V[x] = self.V[y],
+            0 => self.set_v(x, self.V[y]),
             1 => {
-                self.V[x] |= self.V[y];
+                self.set_v(x, self.V[x] | self.V[y]);
                 if self.config.logic_quirk {
-                    self.V[0xF] = 0;
+                    self.set_v(0xF, 0);
                 }
             }
             2 => {
-                self.V[x] &= self.V[y];
+                self.set_v(x, self.V[x] & self.V[y]);
                 if self.config.logic_quirk {
-                    self.V[0xF] = 0;
+                    self.set_v(0xF, 0);
                 }
             }
             3 => {
-                self.V[x] ^= self.V[y];
+                self.set_v(x, self.V[x] ^ self.V[y]);
                 if self.config.logic_quirk {
-                    self.V[0xF] = 0;
+                    self.set_v(0xF, 0);
                 }
             }
             4 => {
                 let (res, overflow) = self.V[x].overflowing_add(self.V[y]);
-                self.V[x] = res;
-                self.V[0xF] = overflow as u8;
+                self.set_with_flag(x, res, overflow as u8);
             }
             5 => {
                 let (res, underflow) = self.V[x].overflowing_sub(self.V[y]);
-                self.V[x] = res;
-                self.V[0xF] = !underflow as u8;
+                self.set_with_flag(x, res, !underflow as u8);
             }
             6 => {
                 let index = if self.config.shift_quirk { x } else { y };
                 let lsb = ((self.V[index] & 0x1) == 0x1) as u8;
-                self.V[x] = self.V[index] >> 1;
-                self.V[0xF] = lsb;
+                self.set_with_flag(x, self.V[index] >> 1, lsb);
             }
             7 => {
                 let (res, underflow) = self.V[y].overflowing_sub(self.V[x]);
-                self.V[x] = res;
-                self.V[0xF] = !underflow as u8;
+                self.set_with_flag(x, res, !underflow as u8);
             }
             0xE => {
                 let index = if self.config.shift_quirk { x } else { y };
                 let msb = ((self.V[index] & 0x80) == 0x80) as u8;
-                self.V[x] = self.V[index] << 1;
-                self.V[0xF] = msb;
+                self.set_with_flag(x, self.V[index] << 1, msb);
+            }
+            _ => self.execute_extension(self.opcode),
+        }
+    }
+    // When X is F, whichever is written last wins
+    fn set_with_flag(&mut self, x: usize, result: u8, flag: u8) {
+        match self.config.flag_first_quirk {
+            true => {
+                self.set_v(0xF, flag);
+                self.set_v(x, result);
+            }
+            false => {
+                self.set_v(x, result);
+                self.set_v(0xF, flag);
             }
-            _ => unreachable!(),
         }
     }
+
     fn set_random(&mut self, x: usize, nn: u8) {
-        self.V[x] = rand::random::<u8>() & nn;
+        self.set_v(x, rand::random::<u8>() & nn);
     }
 
     fn set_i(&mut self, nnn: usize) {
         self.I = nnn;
     }
     fn add_i(&mut self, x: usize) {
-        self.I += self.V[x] as usize & 0xFFF;
+        let sum = self.I + self.V[x] as usize;
+        if self.config.i_overflow_quirk {
+            self.set_v(0xF, (sum > 0xFFF) as u8);
+        }
+
+        self.I = sum & self.config.i_mask;
     }
     fn set_i_sprite(&mut self, x: usize) {
         // VX should be a single hex value (0-F)
-        // Assuming fonts begin at 0x0, each font takes 5 bytes
-        self.I = FONT_LOCATION + (self.V[x] as usize & 0xF) * 5;
+        self.I = self.font_location + (self.V[x] as usize & 0xF) * font::SMALL_GLYPH_BYTES;
     }
     fn set_i_big_sprite(&mut self, x: usize) {
         // VX should be a single hex value (0-F)
-        // Assuming big fonts begin after small fonts, each font takes 10 bytes
-        self.I = BIG_FONT_LOCATION + (self.V[x] as usize & 0xF) * 10;
+        // Some big fonts only have digits, this reads past them like the original did
+        self.I = self.big_font_location + (self.V[x] as usize & 0xF) * font::BIG_GLYPH_BYTES;
     }
     fn set_bcd(&mut self, x: usize) {
         let vx = self.V[x];
 
-        self.memory[self.I] = vx / 100;
-        self.memory[self.I + 1] = vx % 100 / 10;
-        self.memory[self.I + 2] = vx % 100 % 10;
+        self.write_memory(self.I, vx / 100);
+        self.write_memory(self.I + 1, vx % 100 / 10);
+        self.write_memory(self.I + 2, vx % 100 % 10);
+
+        self.sync_memory_to_display(self.I, 3);
     }
 
     fn draw(&mut self, x: usize, y: usize, n: usize) {
@@ -452,14 +1073,13 @@ impl CPU {
         // Slightly counter-intuitive, but we should mod by curr res
         // This is because VX and VY are memory indices
         // They shouldn't be affected by varying size
-        let vx = (self.V[x] as usize) % self.curr_res.0;
-        let vy = (self.V[y] as usize) % self.curr_res.1;
+        let (raw_x, raw_y) = (self.V[x] as usize, self.V[y] as usize);
+        let vx = raw_x % self.curr_res.0;
+        let vy = raw_y % self.curr_res.1;
 
         let x_size = self.max_res.0 / self.curr_res.0;
         let y_size = self.max_res.1 / self.curr_res.1;
 
-        self.V[0xF] = 0;
-
         // Handle DXY0 - change impl depending on DXY0 set width from config
         let (lines, step, width) = if n == 0 && self.config.hires_enabled {
             // Width should be either 8 or 16
@@ -473,87 +1093,181 @@ impl CPU {
             (n, 1, 8)
         };
 
+        // SCHIP's row count includes rows clipped at the bottom
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+
         // Behavior: the starting position should wrap (x & currWidth, y & currHeight)
-        // But the drawing should NOT wrap
+        // The drawing itself only wraps with wrap_quirk, otherwise it's clipped
         for index in (self.I..self.I + lines).step_by(step) {
-            let mut mem_value = self.memory[index] as usize;
+            let mut mem_value = self.read_memory(index) as usize;
             if step == 2 {
                 mem_value <<= 8;
-                mem_value |= self.memory[index + 1] as usize;
+                mem_value |= self.read_memory(index + 1) as usize;
             }
 
             let y_offset = (index - self.I) / step;
-            for x_offset in 0..width {
-                let pixel_value = (1usize << ((width - 1) - x_offset)) & mem_value != 0;
-
-                // Multiply by size to get correct offsets into pixel buffer
-                let y_index = (vy + y_offset) * y_size;
-                let x_index = (vx + x_offset) * x_size;
-
-                // Draw pixels
-                for y_index in y_index..y_index + y_size {
-                    for x_index in x_index..x_index + x_size {
-                        if x_index < self.max_res.0 && y_index < self.max_res.1 {
-                            if let Some(pixel) = self
-                                .pixels
-                                .get_mut(y_index)
-                                .and_then(|row| row.get_mut(x_index))
-                            {
-                                if *pixel && pixel_value {
-                                    self.V[0xF] = 1;
-                                }
-
-                                *pixel ^= pixel_value;
-                            }
-                        }
-                    }
+            let sprite_y = match self.config.wrap_quirk {
+                true => (vy + y_offset) % self.curr_res.1,
+                false if vy + y_offset >= self.curr_res.1 => {
+                    clipped_rows += 1;
+                    continue;
                 }
+                false => vy + y_offset,
+            };
+
+            // Build the row at display scale, the part past the right edge wraps or is clipped
+            let mut mask = [0; ROW_WORDS];
+            let visible = width.min(self.curr_res.0 - vx);
+            let (shown, wrapped) = (mem_value >> (width - visible), width - visible);
+            framebuffer::place(
+                &mut mask,
+                scale_bits(shown, visible, x_size),
+                visible * x_size,
+                vx * x_size,
+            );
+            if self.config.wrap_quirk && wrapped > 0 {
+                let bits = mem_value & ((1 << wrapped) - 1);
+                framebuffer::place(
+                    &mut mask,
+                    scale_bits(bits, wrapped, x_size),
+                    wrapped * x_size,
+                    0,
+                );
+            }
+
+            // Multiply by size to get correct offsets into pixel buffer
+            let mut row_collided = false;
+            for y_index in sprite_y * y_size..(sprite_y + 1) * y_size {
+                row_collided |= self.framebuffer.xor_row(y_index, &mask);
+            }
+
+            if row_collided {
+                collided_rows += 1;
             }
         }
+
+        // SCHIP only counts rows in hires, lores always reports a plain collision flag
+        let collision = match self.config.row_collision_quirk && x_size == 1 {
+            true => collided_rows + clipped_rows,
+            false => (collided_rows > 0) as u8,
+        };
+        self.set_v(0xF, collision);
+
+        self.sync_display_to_memory();
+
+        let draw = Draw {
+            x: vx as u8,
+            y: vy as u8,
+            rows: lines / step,
+            width,
+            address: self.I,
+            collision,
+            wrapped: raw_x >= self.curr_res.0 || raw_y >= self.curr_res.1,
+            crosses_edge: vx + width > self.curr_res.0 || vy + lines / step > self.curr_res.1,
+        };
+        self.notify(|observer| observer.on_draw(&draw));
     }
 
-    fn key_check(&mut self, x: usize, equals: bool) {
-        let vx = self.V[x];
+    // CHIP-8X BXYN, colour is VY and the area is given by VX (columns) and VX+1 (rows)
+    fn set_color(&mut self, x: usize, y: usize, n: usize) {
+        let (vx, vx1) = (self.V[x] as usize, self.V[(x + 1) & 0xF] as usize);
+        let color = self.V[y];
+        self.framebuffer.set_dirty(true);
+
+        match n {
+            // BXY0: low nibbles are the first zone, high nibbles are how many more to fill
+            0 => self.color_map.fill(
+                (vx & 0xF, (vx >> 4) + 1),
+                (
+                    (vx1 & 0xF) * color::ZONE_HEIGHT,
+                    ((vx1 >> 4) + 1) * color::ZONE_HEIGHT,
+                ),
+                color,
+            ),
+            // BXYN: a single zone column, N rows starting at VX+1
+            _ => self.color_map.fill(
+                ((vx / color::ZONE_WIDTH) % color::ZONE_COLUMNS, 1),
+                (vx1 % self.max_res.1, n),
+                color,
+            ),
+        }
+    }
+
+    fn key_check(&mut self, x: usize, keypad: usize, equals: bool) {
+        let vx = self.V[x] as usize & 0xF;
         // println!("Checking input, x = {}, vx = {}, key state = {:#x}", x, vx, self.key);
+        let key = 1u32 << (keypad * 16 + vx);
         if match equals {
-            true => (1u16 << vx) & self.keys != 0,
-            false => (1u16 << vx) & self.keys == 0,
+            true => key & self.keys != 0,
+            false => key & self.keys == 0,
         } {
             self.pc += 2;
         }
     }
     fn get_key(&mut self, x: usize) {
-        self.polling_key_press = PollingKeyPress::Polling(x);
+        self.polling_key_press = PollingKeyPress::Polling(x, 0);
     }
 
     fn set_delay(&mut self, x: usize) {
-        self.delay_timer = self.V[x];
+        self.set_timer(Timer::Delay, self.V[x]);
     }
     fn set_sound(&mut self, x: usize) {
-        self.sound_timer = self.V[x];
+        let value = match self.V[x] < self.config.min_sound_timer {
+            true => 0,
+            false => self.V[x],
+        };
+        self.set_timer(Timer::Sound, value);
     }
 
     fn reg_dump(&mut self, x: usize) {
         for x_index in 0..=x {
-            self.memory[self.I + x_index] = self.V[x_index];
+            self.write_memory(self.I + x_index, self.V[x_index]);
         }
+        self.sync_memory_to_display(self.I, x + 1);
 
         if let Some(offset) = self.config.load_store_offset {
-            self.I += x;
-            self.I += offset;
+            self.I = (self.I + x + offset) & self.config.i_mask;
         }
     }
     fn reg_load(&mut self, x: usize) {
         for x_index in 0..=x {
-            self.V[x_index] = self.memory[self.I + x_index];
+            let value = self.read_memory(self.I + x_index);
+            self.set_v(x_index, value);
         }
 
         if let Some(offset) = self.config.load_store_offset {
-            self.I += x;
-            self.I += offset;
+            self.I = (self.I + x + offset) & self.config.i_mask;
+        }
+    }
+
+    // VX..VY inclusive (in reverse if X > Y), I is left unchanged
+    fn save_range(&mut self, x: usize, y: usize) {
+        let registers = register_range(x, y);
+
+        for (offset, &index) in registers.iter().enumerate() {
+            self.write_memory(self.I + offset, self.V[index]);
+        }
+        self.sync_memory_to_display(self.I, registers.len());
+    }
+    fn load_range(&mut self, x: usize, y: usize) {
+        for (offset, index) in register_range(x, y).into_iter().enumerate() {
+            let value = self.read_memory(self.I + offset);
+            self.set_v(index, value);
         }
     }
 
+    fn port_read(&mut self, x: usize, wait_for_strobe: bool) {
+        if wait_for_strobe && !self.port_strobe {
+            // Run this instruction again until input arrives
+            self.pc -= 2;
+            return;
+        }
+
+        self.set_v(x, self.port_input);
+        self.port_strobe = false;
+    }
+
     fn flag_dump(&mut self, x: usize) {
         for x_index in 0..=x {
             self.flag_registers[x_index] = self.V[x_index];
@@ -561,7 +1275,26 @@ impl CPU {
     }
     fn flag_load(&mut self, x: usize) {
         for x_index in 0..=x {
-            self.V[x_index] = self.flag_registers[x_index];
+            self.set_v(x_index, self.flag_registers[x_index]);
         }
     }
 }
+
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    match x <= y {
+        true => (x..=y).collect(),
+        false => (y..=x).rev().collect(),
+    }
+}
+
+// Each of the `width` bits repeated `scale` times, for lores sprites on a hires framebuffer
+fn scale_bits(bits: usize, width: usize, scale: usize) -> u128 {
+    if scale == 1 {
+        return bits as u128;
+    }
+
+    (0..width).fold(0, |scaled, bit| {
+        let pixel = (bits >> (width - 1 - bit)) as u128 & 1;
+        (scaled << scale) | (pixel * ((1 << scale) - 1))
+    })
+}
@@ -0,0 +1,81 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::CPU;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timer {
+    Delay,
+    Sound,
+}
+
+// A DXYN that just ran, x/y is the (wrapped) start position and rows is how many sprite rows were read from I
+#[derive(Clone, Copy, Debug)]
+pub struct Draw {
+    pub x: u8,
+    pub y: u8,
+    pub rows: usize,
+    pub width: usize, // 8, or 16 for a DXY0 big sprite
+    pub address: usize,
+    pub collision: u8,      // VF after the draw
+    pub wrapped: bool,      // VX/VY were off-screen, so the start position wrapped around
+    pub crosses_edge: bool, // Part of the sprite was clipped (or wrapped, with wrap_quirk)
+}
+
+// Hooks into CPU::process, for debuggers, profilers, coverage and the like
+// Everything does nothing by default, so only implement what you need
+// Memory and register hooks are passed the opcode that caused the access
+pub trait Observer {
+    fn on_fetch(&mut self, _pc: usize, _opcode: u16) {}
+    fn on_execute(&mut self, _cpu: &CPU, _pc: usize, _opcode: u16) {} // After the instruction ran
+    fn on_memory_read(&mut self, _address: usize, _value: u8, _opcode: u16) {}
+    fn on_memory_write(&mut self, _address: usize, _value: u8, _opcode: u16) {}
+    fn on_register_write(&mut self, _register: usize, _value: u8, _opcode: u16) {}
+    fn on_call(&mut self, _from: usize, _to: usize) {}
+    fn on_return(&mut self, _from: usize, _to: usize) {}
+    fn on_draw(&mut self, _draw: &Draw) {}
+    fn on_timer(&mut self, _timer: Timer, _value: u8) {}
+    fn on_frame(&mut self) {} // Once per 60Hz timer tick
+}
+
+// Lets the caller keep a handle on an observer after giving it to the CPU
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn on_fetch(&mut self, pc: usize, opcode: u16) {
+        self.borrow_mut().on_fetch(pc, opcode);
+    }
+
+    fn on_execute(&mut self, cpu: &CPU, pc: usize, opcode: u16) {
+        self.borrow_mut().on_execute(cpu, pc, opcode);
+    }
+
+    fn on_memory_read(&mut self, address: usize, value: u8, opcode: u16) {
+        self.borrow_mut().on_memory_read(address, value, opcode);
+    }
+
+    fn on_memory_write(&mut self, address: usize, value: u8, opcode: u16) {
+        self.borrow_mut().on_memory_write(address, value, opcode);
+    }
+
+    fn on_register_write(&mut self, register: usize, value: u8, opcode: u16) {
+        self.borrow_mut().on_register_write(register, value, opcode);
+    }
+
+    fn on_call(&mut self, from: usize, to: usize) {
+        self.borrow_mut().on_call(from, to);
+    }
+
+    fn on_return(&mut self, from: usize, to: usize) {
+        self.borrow_mut().on_return(from, to);
+    }
+
+    fn on_draw(&mut self, draw: &Draw) {
+        self.borrow_mut().on_draw(draw);
+    }
+
+    fn on_timer(&mut self, timer: Timer, value: u8) {
+        self.borrow_mut().on_timer(timer, value);
+    }
+
+    fn on_frame(&mut self) {
+        self.borrow_mut().on_frame();
+    }
+}
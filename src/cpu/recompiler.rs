@@ -0,0 +1,377 @@
+// Ahead of time translation of a ROM into Rust source, one function per basic block
+// Blocks cover the same register-only code the JIT handles, anything else is interpreted
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Write},
+};
+
+use super::{config::CPUConfig, CPUVariant, Instruction, QuirkOverrides, CPU};
+
+// Longest run of instructions translated into one block
+pub(super) const MAX_BLOCK_LEN: usize = 64;
+
+// A translated block, as emitted by CPU::recompile
+// The block only runs while memory still holds code, otherwise it's interpreted
+pub struct RecompiledBlock {
+    pub start: usize,
+    pub code: &'static [u8],
+    pub len: usize, // Instructions
+    pub last_opcode: u16,
+    pub run: fn(&mut [u8; 0x10], &mut usize) -> usize, // Takes V and I, returns the new PC
+}
+
+pub(super) enum Kind {
+    Straight,
+    Terminator, // Changes PC, ends the block
+    Unsupported,
+}
+
+// Only opcodes the interpreter runs the same way on every variant with these quirks
+pub(super) fn classify(opcode: u16, config: &CPUConfig) -> Kind {
+    let nnn = opcode & 0xFFF;
+    match (opcode >> 12, opcode & 0xF, opcode & 0xFF) {
+        // "1260" can switch hires ROMs into hires mode
+        (1, _, _) if config.hires_entry.is_some() && nnn == 0x260 => Kind::Unsupported,
+        (1, _, _) | (3, _, _) | (4, _, _) | (5, 0, _) | (9, 0, _) => Kind::Terminator,
+        (6, _, _) | (7, _, _) | (0xA, _, _) => Kind::Straight,
+        (8, 0..=7 | 0xE, _) => Kind::Straight,
+        (0xB, _, _) if !config.chip8e_enabled && !config.color_enabled => Kind::Terminator,
+        (0xF, _, 0x1E) => Kind::Straight,
+        _ => Kind::Unsupported,
+    }
+}
+
+impl CPU {
+    // Rust module for a ROM, to be built against this crate and run with CPU::run_recompiled
+    // The variant is needed to recreate the CPU, quirks come from this CPU
+    pub fn recompile(&self, variant: CPUVariant, rom: &[u8]) -> String {
+        let start = self.config.pc_start;
+        let fetch = |address: usize| -> Option<u16> {
+            let offset = address.checked_sub(start)?;
+            Some(((*rom.get(offset)? as u16) << 8) | *rom.get(offset + 1)? as u16)
+        };
+
+        let blocks: Vec<(usize, Vec<u16>)> = self
+            .leaders(&fetch)
+            .into_iter()
+            .filter_map(|leader| {
+                let mut opcodes = vec![];
+                for address in (leader..).step_by(2).take(MAX_BLOCK_LEN) {
+                    let Some(opcode) = fetch(address) else {
+                        break;
+                    };
+                    match classify(opcode, &self.config) {
+                        Kind::Unsupported => break,
+                        Kind::Straight => opcodes.push(opcode),
+                        Kind::Terminator => {
+                            opcodes.push(opcode);
+                            break;
+                        }
+                    }
+                }
+                (!opcodes.is_empty()).then_some((leader, opcodes))
+            })
+            .collect();
+
+        let mut source = String::new();
+        self.write_module(&mut source, variant, rom, &blocks)
+            .unwrap();
+        source
+    }
+
+    // Addresses where a block can start: the entry point, branch targets, return addresses
+    // and whatever follows an interpreted instruction
+    fn leaders(&self, fetch: &impl Fn(usize) -> Option<u16>) -> BTreeSet<usize> {
+        let mut leaders = BTreeSet::from([self.config.pc_start]);
+        let mut visited = BTreeSet::new();
+
+        let mut pending = vec![self.config.pc_start];
+        while let Some(address) = pending.pop() {
+            if !visited.insert(address) {
+                continue;
+            }
+            let Some(opcode) = fetch(address) else {
+                continue;
+            };
+            let next = address + 2;
+
+            let targets = match Instruction::decode(opcode) {
+                Instruction::Jump(0x260)
+                    if address == self.config.pc_start && self.config.hires_entry.is_some() =>
+                {
+                    vec![self.config.hires_entry.unwrap()]
+                }
+                Instruction::Jump(nnn) => vec![nnn as usize],
+                Instruction::Call(nnn) => vec![nnn as usize, next],
+                Instruction::JumpOffset(_)
+                | Instruction::Return
+                | Instruction::Exit
+                | Instruction::Unknown(_) => vec![],
+                instruction if instruction.is_skip() => vec![next, next + 2],
+                _ => {
+                    if let Kind::Unsupported = classify(opcode, &self.config) {
+                        leaders.insert(next);
+                    }
+                    pending.push(next);
+                    continue;
+                }
+            };
+
+            leaders.extend(&targets);
+            pending.extend(targets);
+        }
+        leaders
+    }
+
+    fn write_module(
+        &self,
+        source: &mut String,
+        variant: CPUVariant,
+        rom: &[u8],
+        blocks: &[(usize, Vec<u16>)],
+    ) -> fmt::Result {
+        let config = &self.config;
+
+        writeln!(
+            source,
+            "// Generated by chip8-emulator-rs recompile, do not edit"
+        )?;
+        writeln!(source, "#![allow(non_snake_case, unused_variables)]")?;
+        writeln!(source)?;
+        writeln!(
+            source,
+            "use chip8_emulator_rs::cpu::{{CPUVariant, OutOfBounds, QuirkOverrides, RecompiledBlock, CPU}};"
+        )?;
+        writeln!(source)?;
+        writeln!(source, "pub const ROM: &[u8] = &{};", bytes(rom))?;
+        writeln!(source)?;
+
+        // Same quirks as this CPU, the blocks depend on some of them
+        writeln!(source, "pub fn cpu() -> CPU {{")?;
+        writeln!(
+            source,
+            "    let mut cpu = CPU::new(CPUVariant::{:?});",
+            variant
+        )?;
+        // No `..` here, so a new override can't be left out of the module
+        let QuirkOverrides {
+            logic_quirk,
+            shift_quirk,
+            jump_quirk,
+            vblank_quirk,
+            wrap_quirk,
+            i_overflow_quirk,
+            key_wait_on_press,
+            flag_first_quirk,
+            min_sound_timer,
+            load_store_offset,
+            memory_size,
+            out_of_bounds,
+            read_only_interpreter,
+        } = config.overrides();
+        let out_of_bounds = match out_of_bounds {
+            Some(out_of_bounds) => format!("Some(OutOfBounds::{:?})", out_of_bounds),
+            None => String::from("None"),
+        };
+        let fields = [
+            ("logic_quirk", format!("{:?}", logic_quirk)),
+            ("shift_quirk", format!("{:?}", shift_quirk)),
+            ("jump_quirk", format!("{:?}", jump_quirk)),
+            ("vblank_quirk", format!("{:?}", vblank_quirk)),
+            ("wrap_quirk", format!("{:?}", wrap_quirk)),
+            ("i_overflow_quirk", format!("{:?}", i_overflow_quirk)),
+            ("key_wait_on_press", format!("{:?}", key_wait_on_press)),
+            ("flag_first_quirk", format!("{:?}", flag_first_quirk)),
+            ("min_sound_timer", format!("{:?}", min_sound_timer)),
+            ("load_store_offset", format!("{:?}", load_store_offset)),
+            ("memory_size", format!("{:?}", memory_size)),
+            ("out_of_bounds", out_of_bounds),
+            (
+                "read_only_interpreter",
+                format!("{:?}", read_only_interpreter),
+            ),
+        ];
+
+        writeln!(source, "    cpu.apply_quirks(&QuirkOverrides {{")?;
+        for (name, value) in fields {
+            writeln!(source, "        {}: {},", name, value)?;
+        }
+        writeln!(source, "    }});")?;
+        writeln!(source, "    cpu.load_rom_bytes(ROM);")?;
+        writeln!(source, "    cpu")?;
+        writeln!(source, "}}")?;
+        writeln!(source)?;
+
+        writeln!(source, "pub fn run(cpu: &mut CPU, cycles: usize) {{")?;
+        writeln!(source, "    cpu.run_recompiled(cycles, block);")?;
+        writeln!(source, "}}")?;
+        writeln!(source)?;
+
+        writeln!(
+            source,
+            "pub fn block(pc: usize) -> Option<&'static RecompiledBlock> {{"
+        )?;
+        writeln!(source, "    match pc {{")?;
+        for (start, _) in blocks {
+            writeln!(
+                source,
+                "        {:#05X} => Some(&BLOCK_{:03X}),",
+                start, start
+            )?;
+        }
+        writeln!(source, "        _ => None,")?;
+        writeln!(source, "    }}")?;
+        writeln!(source, "}}")?;
+
+        for (start, opcodes) in blocks {
+            let code: Vec<u8> = opcodes
+                .iter()
+                .flat_map(|opcode| opcode.to_be_bytes())
+                .collect();
+
+            writeln!(source)?;
+            writeln!(
+                source,
+                "static BLOCK_{:03X}: RecompiledBlock = RecompiledBlock {{",
+                start
+            )?;
+            writeln!(source, "    start: {:#05X},", start)?;
+            writeln!(source, "    code: &{},", bytes(&code))?;
+            writeln!(source, "    len: {},", opcodes.len())?;
+            writeln!(source, "    last_opcode: {:#06X},", opcodes.last().unwrap())?;
+            writeln!(source, "    run: block_{:03x},", start)?;
+            writeln!(source, "}};")?;
+            writeln!(source)?;
+
+            writeln!(
+                source,
+                "fn block_{:03x}(V: &mut [u8; 0x10], I: &mut usize) -> usize {{",
+                start
+            )?;
+            for (index, &opcode) in opcodes.iter().enumerate() {
+                writeln!(
+                    source,
+                    "    // {:#05X}: {:04X}  {}",
+                    start + index * 2,
+                    opcode,
+                    Instruction::decode(opcode)
+                )?;
+                for line in self.translate(start + index * 2, opcode) {
+                    writeln!(source, "    {}", line)?;
+                }
+            }
+            // Blocks cut short by an instruction that gets interpreted carry on after the last one
+            let last = *opcodes.last().unwrap();
+            if let Kind::Straight = classify(last, config) {
+                writeln!(source, "    {:#05X}", start + opcodes.len() * 2)?;
+            }
+            writeln!(source, "}}")?;
+        }
+        Ok(())
+    }
+
+    // Statements for one instruction, the same steps as the interpreter takes
+    // Jumps and skips end the block, they become its return value
+    fn translate(&self, pc: usize, opcode: u16) -> Vec<String> {
+        let config = &self.config;
+        let x = (opcode >> 8) as usize & 0xF;
+        let y = (opcode >> 4) as usize & 0xF;
+        let nn = opcode & 0xFF;
+        let nnn = opcode & 0xFFF;
+
+        // Same write order as CPU::set_with_flag
+        let with_flag = |result: String, flag: String| -> Vec<String> {
+            let writes = match config.flag_first_quirk {
+                true => format!("V[0xF] = flag; V[{:#X}] = result;", x),
+                false => format!("V[{:#X}] = result; V[0xF] = flag;", x),
+            };
+            vec![format!(
+                "{{ let (result, flag) = ({}, {}); {} }}",
+                result, flag, writes
+            )]
+        };
+        let shifted = match config.shift_quirk {
+            true => x,
+            false => y,
+        };
+
+        match (opcode >> 12, opcode & 0xF) {
+            (1, _) => vec![format!("{:#05X}", nnn)],
+            (3 | 4 | 5 | 9, _) => {
+                let check = match opcode >> 12 {
+                    3 | 4 => format!("{:#04X}", nn),
+                    _ => format!("V[{:#X}]", y),
+                };
+                let op = match opcode >> 12 {
+                    3 | 5 => "==",
+                    _ => "!=",
+                };
+                vec![format!(
+                    "if V[{:#X}] {} {} {{ {:#05X} }} else {{ {:#05X} }}",
+                    x,
+                    op,
+                    check,
+                    pc + 4,
+                    pc + 2
+                )]
+            }
+            (6, _) => vec![format!("V[{:#X}] = {:#04X};", x, nn)],
+            (7, _) => vec![format!(
+                "V[{:#X}] = V[{:#X}].wrapping_add({:#04X});",
+                x, x, nn
+            )],
+            (8, 0) => vec![format!("V[{:#X}] = V[{:#X}];", x, y)],
+            (8, code @ 1..=3) => {
+                let op = ["|", "&", "^"][code as usize - 1];
+                let mut lines = vec![format!("V[{:#X}] {}= V[{:#X}];", x, op, y)];
+                if config.logic_quirk {
+                    lines.push(String::from("V[0xF] = 0;"));
+                }
+                lines
+            }
+            (8, 4) => with_flag(
+                format!("V[{:#X}].wrapping_add(V[{:#X}])", x, y),
+                format!("V[{:#X}].checked_add(V[{:#X}]).is_none() as u8", x, y),
+            ),
+            (8, 5) => with_flag(
+                format!("V[{:#X}].wrapping_sub(V[{:#X}])", x, y),
+                format!("(V[{:#X}] >= V[{:#X}]) as u8", x, y),
+            ),
+            (8, 6) => with_flag(
+                format!("V[{:#X}] >> 1", shifted),
+                format!("V[{:#X}] & 0x1", shifted),
+            ),
+            (8, 7) => with_flag(
+                format!("V[{:#X}].wrapping_sub(V[{:#X}])", y, x),
+                format!("(V[{:#X}] >= V[{:#X}]) as u8", y, x),
+            ),
+            (8, _) => with_flag(
+                format!("V[{:#X}] << 1", shifted),
+                format!("V[{:#X}] >> 7", shifted),
+            ),
+            (0xA, _) => vec![format!("*I = {:#05X};", nnn)],
+            (0xB, _) => {
+                let offset = match config.jump_quirk {
+                    true => (nnn as usize >> 8) & 0xF,
+                    false => 0,
+                };
+                vec![format!("{:#05X} + V[{:#X}] as usize", nnn, offset)]
+            }
+            _ => {
+                // FX1E
+                let mut lines = vec![format!("let sum = *I + V[{:#X}] as usize;", x)];
+                if config.i_overflow_quirk {
+                    lines.push(String::from("V[0xF] = (sum > 0xFFF) as u8;"));
+                }
+                lines.push(format!("*I = sum & {:#X};", config.i_mask));
+                lines
+            }
+        }
+    }
+}
+
+fn bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+    format!("[{}]", bytes.join(", "))
+}
@@ -0,0 +1,525 @@
+use super::*;
+
+// Runs `steps` instructions of `rom` on a fresh CPU with the given quirks
+fn run(variant: CPUVariant, quirks: QuirkOverrides, rom: &[u8], steps: usize) -> CPU {
+    let mut cpu = CPU::new(variant);
+    cpu.apply_quirks(&quirks);
+    cpu.load_rom_bytes(rom);
+    for _ in 0..steps {
+        cpu.process();
+    }
+    cpu
+}
+
+// Registers, I, PC and display, for comparing two ways of running the same ROM
+fn state(cpu: &CPU) -> ([u8; 0x10], usize, usize, Vec<Vec<u64>>) {
+    let display = cpu.display();
+    let rows = (0..display.height()).map(|y| display.row(y).to_vec());
+    (*cpu.registers(), cpu.index(), cpu.pc(), rows.collect())
+}
+
+fn polling(cpu: &CPU) -> bool {
+    matches!(cpu.polling_key_press, PollingKeyPress::Polling(..))
+}
+
+// AFFF, V0 = 1, VF = 0x55, FX1E
+const ADD_I_PAST_FFF: &[u8] = &[0xAF, 0xFF, 0x60, 0x01, 0x6F, 0x55, 0xF0, 0x1E];
+
+#[test]
+fn i_overflow_sets_vf() {
+    let quirks = QuirkOverrides {
+        i_overflow_quirk: Some(true),
+        ..Default::default()
+    };
+    let cpu = run(CPUVariant::Chip8, quirks, ADD_I_PAST_FFF, 4);
+    assert_eq!(cpu.registers()[0xF], 1);
+
+    let quirks = QuirkOverrides {
+        i_overflow_quirk: Some(false),
+        ..Default::default()
+    };
+    let cpu = run(CPUVariant::Chip8, quirks, ADD_I_PAST_FFF, 4);
+    assert_eq!(cpu.registers()[0xF], 0x55);
+}
+
+#[test]
+fn i_mask_wraps_i() {
+    let cpu = run(
+        CPUVariant::Chip8,
+        QuirkOverrides::default(),
+        ADD_I_PAST_FFF,
+        4,
+    );
+    assert_eq!(cpu.index(), 0x000);
+
+    let cpu = run(
+        CPUVariant::XOChip,
+        QuirkOverrides::default(),
+        ADD_I_PAST_FFF,
+        4,
+    );
+    assert_eq!(cpu.index(), 0x1000);
+}
+
+#[test]
+fn flag_first_with_vf_as_target() {
+    // VF = 0xFF, V1 = 1, VF += V1: result 0x00, carry 1
+    let rom = &[0x6F, 0xFF, 0x61, 0x01, 0x8F, 0x14];
+
+    let quirks = QuirkOverrides {
+        flag_first_quirk: Some(true),
+        ..Default::default()
+    };
+    let cpu = run(CPUVariant::Chip8, quirks, rom, 3);
+    assert_eq!(cpu.registers()[0xF], 0x00);
+
+    let quirks = QuirkOverrides {
+        flag_first_quirk: Some(false),
+        ..Default::default()
+    };
+    let cpu = run(CPUVariant::Chip8, quirks, rom, 3);
+    assert_eq!(cpu.registers()[0xF], 0x01);
+}
+
+#[test]
+fn min_sound_timer_ignores_short_sounds() {
+    let quirks = || QuirkOverrides {
+        min_sound_timer: Some(2),
+        ..Default::default()
+    };
+
+    let cpu = run(CPUVariant::Chip8, quirks(), &[0x60, 0x01, 0xF0, 0x18], 2);
+    assert_eq!(cpu.timers().1, 0);
+    assert!(!cpu.is_sound_active());
+
+    let cpu = run(CPUVariant::Chip8, quirks(), &[0x60, 0x02, 0xF0, 0x18], 2);
+    assert_eq!(cpu.timers().1, 2);
+    assert!(cpu.is_sound_active());
+}
+
+#[test]
+fn key_wait_on_press() {
+    let quirks = QuirkOverrides {
+        key_wait_on_press: Some(true),
+        ..Default::default()
+    };
+    let mut cpu = run(CPUVariant::Chip8, quirks, &[0xF0, 0x0A], 1);
+    assert!(polling(&cpu));
+
+    cpu.press_key(5);
+    assert!(!polling(&cpu));
+    assert_eq!(cpu.registers()[0], 5);
+}
+
+#[test]
+fn key_wait_on_release() {
+    let quirks = QuirkOverrides {
+        key_wait_on_press: Some(false),
+        ..Default::default()
+    };
+    let mut cpu = run(CPUVariant::Chip8, quirks, &[0xF0, 0x0A], 1);
+
+    cpu.press_key(5);
+    assert!(polling(&cpu));
+
+    cpu.release_key(5);
+    assert!(!polling(&cpu));
+    assert_eq!(cpu.registers()[0], 5);
+}
+
+#[test]
+fn key_held_before_wait_does_not_count() {
+    let quirks = QuirkOverrides {
+        key_wait_on_press: Some(false),
+        ..Default::default()
+    };
+    let mut cpu = CPU::new(CPUVariant::Chip8);
+    cpu.apply_quirks(&quirks);
+    cpu.load_rom_bytes(&[0xF0, 0x0A]);
+
+    cpu.press_key(3);
+    cpu.process();
+    assert!(polling(&cpu));
+
+    cpu.release_key(3);
+    assert!(polling(&cpu));
+
+    cpu.press_key(7);
+    cpu.release_key(7);
+    assert!(!polling(&cpu));
+    assert_eq!(cpu.registers()[0], 7);
+}
+
+#[test]
+fn i_mask_follows_memory_size() {
+    // I = 0xFFF, then add 0xFF to I 0x101 times: 0xFFF + 0xFFFF = 0x10FFE
+    let mut rom = vec![0xAF, 0xFF, 0x60, 0xFF];
+    rom.extend([0xF0, 0x1E].repeat(0x101));
+    let quirks = QuirkOverrides {
+        memory_size: Some(MEMORY_SIZE_MEGACHIP),
+        ..Default::default()
+    };
+    let cpu = run(CPUVariant::XOChip, quirks, &rom, 2 + 0x101);
+    assert_eq!(cpu.index(), 0x10FFE);
+}
+
+#[test]
+fn break_hook_reports_breakpoint() {
+    let mut cpu = CPU::new(CPUVariant::Chip8);
+    cpu.set_debug_hook(0x123, DebugAction::Break);
+    cpu.load_rom_bytes(&[0x60, 0x2A, 0x01, 0x23]);
+    cpu.process();
+    cpu.process();
+
+    assert!(cpu.is_paused());
+    let breakpoint = cpu.take_breakpoint().unwrap();
+    assert_eq!(breakpoint.address, 0x202);
+    assert!(breakpoint.registers.starts_with("v0=2A"));
+    assert_eq!(cpu.take_breakpoint(), None);
+}
+
+// Claims 9XY1 as "VX = VY + 1"
+struct NineExtension;
+
+impl Extension for NineExtension {
+    fn claims(&self, opcode: u16) -> bool {
+        opcode & 0xF00F == 0x9001
+    }
+
+    fn execute(&mut self, opcode: u16, machine: &mut Machine) {
+        let (x, y) = ((opcode >> 8) as usize & 0xF, (opcode >> 4) as usize & 0xF);
+        machine.set_v(x, machine.v(y) + 1);
+    }
+}
+
+#[test]
+fn nine_xyn_goes_to_extensions() {
+    // V1 = 5, V0 = V1 + 1, skip if V0 != V1, V2 = 1 (skipped)
+    let rom = &[0x61, 0x05, 0x90, 0x11, 0x90, 0x10, 0x62, 0x01];
+    let mut cpu = CPU::with_extensions(CPUVariant::Chip8, vec![Box::new(NineExtension)]);
+    cpu.load_rom_bytes(rom);
+    for _ in 0..3 {
+        cpu.process();
+    }
+
+    assert_eq!(cpu.registers()[0], 6);
+    assert_eq!(cpu.pc(), 0x208);
+    assert!(matches!(
+        recompiler::classify(0x9011, &cpu.config),
+        recompiler::Kind::Unsupported
+    ));
+    assert!(matches!(
+        recompiler::classify(0x9010, &cpu.config),
+        recompiler::Kind::Terminator
+    ));
+}
+
+// Runs the ROM with and without the decode cache, both must end up in the same state
+fn run_uncached(variant: CPUVariant, rom: &[u8], steps: usize) -> CPU {
+    let cached = run(variant, QuirkOverrides::default(), rom, steps);
+
+    let mut uncached = CPU::new(variant);
+    uncached.set_decode_cache(false);
+    uncached.load_rom_bytes(rom);
+    for _ in 0..steps {
+        uncached.process();
+    }
+
+    assert_eq!(state(&cached), state(&uncached));
+    cached
+}
+
+#[test]
+fn decode_cache_sees_fx55_writes() {
+    #[rustfmt::skip]
+    let rom = &[
+        0x6A, 0x01, // 200: VA = 1
+        0x7A, 0x01, // 202: VA += 1, patched to VA += 0x10
+        0x4A, 0x02, // 204: skip if VA != 2
+        0x12, 0x0A, // 206: jump 20A
+        0x12, 0x08, // 208: loop
+        0x60, 0x7A, // 20A: V0 = 7A
+        0x61, 0x10, // 20C: V1 = 10
+        0xA2, 0x02, // 20E: I = 202
+        0xF1, 0x55, // 210: store V0-V1
+        0x12, 0x02, // 212: jump 202
+    ];
+    let cpu = run_uncached(CPUVariant::Chip8, rom, 20);
+    assert_eq!(cpu.registers()[0xA], 0x12);
+    assert_eq!(cpu.pc(), 0x208);
+}
+
+#[test]
+fn decode_cache_sees_display_memory() {
+    // DREAM 6800 display memory starts at 0x100, drawing there rewrites the code
+    #[rustfmt::skip]
+    let rom = &[
+        0x60, 0x00, // 200: V0 = 00
+        0x61, 0x00, // 202: V1 = 00
+        0x62, 0x00, // 204: V2 = 00
+        0x63, 0xEE, // 206: V3 = EE
+        0xA1, 0x00, // 208: I = 100
+        0xF3, 0x55, // 20A: store 0000 00EE at 100
+        0x6A, 0x77, // 20C: VA = 77
+        0x21, 0x00, // 20E: call 100
+        0xA2, 0x1C, // 210: I = sprite
+        0x6B, 0x00, // 212: VB = 0
+        0xDB, 0xB1, // 214: draw at 0,0, turning 100 into 6A00 (VA = 0)
+        0x21, 0x00, // 216: call 100
+        0x12, 0x18, // 218: loop
+        0x00, 0x00, // 21A
+        0x6A,       // 21C: sprite
+    ];
+    let cpu = run_uncached(CPUVariant::Dream6800, rom, 20);
+    assert_eq!(cpu.registers()[0xA], 0x00);
+    assert_eq!(cpu.pc(), 0x218);
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn jit_matches_interpreter() {
+    #[rustfmt::skip]
+    let rom = &[
+        0x60, 0x00, // 200: V0 = 0
+        0x61, 0x00, // 202: V1 = 0
+        0x65, 0x00, // 204: V5 = 0
+        0x70, 0x01, // 206: V0 += 1
+        0x81, 0x04, // 208: V1 += V0
+        0x82, 0x06, // 20A: V2 = V0 >> 1
+        0x83, 0x0E, // 20C: V3 = V0 << 1
+        0x83, 0x13, // 20E: V3 ^= V1
+        0xF0, 0x1E, // 210: I += V0
+        0x30, 0x00, // 212: skip if V0 == 0
+        0x12, 0x06, // 214: jump 206
+        0xF4, 0x29, // 216: I = digit V4
+        0xD5, 0x65, // 218: draw at V5, V6
+        0x74, 0x01, // 21A: V4 += 1
+        0x75, 0x08, // 21C: V5 += 8
+        0x12, 0x06, // 21E: jump 206
+    ];
+    let quirks = || QuirkOverrides {
+        vblank_quirk: Some(false),
+        ..Default::default()
+    };
+    let cycles = 5000;
+
+    let interpreted = run(CPUVariant::Chip8, quirks(), rom, cycles);
+
+    let mut jit = CPU::new(CPUVariant::Chip8);
+    jit.apply_quirks(&quirks());
+    jit.enable_jit().unwrap();
+    jit.load_rom_bytes(rom);
+    jit.run(cycles);
+
+    assert_eq!(state(&interpreted), state(&jit));
+}
+
+#[test]
+fn font_location_follows_variant() {
+    // V0 = 2, I = digit V0
+    let rom = &[0x60, 0x02, 0xF0, 0x29];
+
+    let cpu = run(CPUVariant::Chip8, QuirkOverrides::default(), rom, 2);
+    assert_eq!(cpu.font_range().start, 0x50);
+    assert_eq!(cpu.index(), 0x50 + 2 * 5);
+
+    let cpu = run(CPUVariant::XOChip, QuirkOverrides::default(), rom, 2);
+    assert_eq!(cpu.font_range().start, 0x0);
+    assert_eq!(cpu.index(), 2 * 5);
+}
+
+#[test]
+fn stack_faults_stop_the_cpu() {
+    // Calls itself until the stack is full
+    let cpu = run(
+        CPUVariant::Chip8,
+        QuirkOverrides::default(),
+        &[0x22, 0x00],
+        17,
+    );
+    assert!(!cpu.running);
+    assert_eq!(cpu.fault(), Some(Fault::StackOverflow { address: 0x200 }));
+
+    let cpu = run(
+        CPUVariant::Chip8,
+        QuirkOverrides::default(),
+        &[0x00, 0xEE],
+        1,
+    );
+    assert!(!cpu.running);
+    assert_eq!(cpu.fault(), Some(Fault::StackUnderflow { address: 0x200 }));
+}
+
+// Claims 5XY1 and 5XY2, "VX = 0xEE", and 0x080, "write VX to I"
+struct FiveExtension;
+
+impl Extension for FiveExtension {
+    fn claims(&self, opcode: u16) -> bool {
+        matches!(opcode & 0xF00F, 0x5001 | 0x5002) || opcode & 0xF0FF == 0x0080
+    }
+
+    fn execute(&mut self, opcode: u16, machine: &mut Machine) {
+        let x = (opcode >> 8) as usize & 0xF;
+        match opcode >> 12 {
+            0 => machine.write(machine.i(), machine.v(x)),
+            _ => machine.set_v(x, 0xEE),
+        }
+    }
+
+    fn disassemble(&self, opcode: u16) -> Option<String> {
+        Some(format!("five {:04X}", opcode))
+    }
+}
+
+fn with_five(variant: CPUVariant, rom: &[u8], steps: usize) -> CPU {
+    let mut cpu = CPU::with_extensions(variant, vec![Box::new(FiveExtension)]);
+    cpu.load_rom_bytes(rom);
+    for _ in 0..steps {
+        cpu.process();
+    }
+    cpu
+}
+
+#[test]
+fn decoding_follows_routing() {
+    // 5XY2 isn't a CHIP-8 instruction, so the extension gets it
+    let cpu = with_five(CPUVariant::Chip8, &[0x51, 0x22], 1);
+    assert_eq!(cpu.registers()[1], 0xEE);
+    assert_eq!(cpu.decode(0x5122), Instruction::Custom(0x5122));
+    assert_eq!(cpu.disassemble(0x5122), "five 5122");
+    assert_eq!(cpu.decode(0x5123), Instruction::Unknown(0x5123));
+
+    // CHIP-8E runs 5XY1 itself (skip if VX > VY), claimed or not
+    let cpu = with_five(CPUVariant::Chip8E, &[0x61, 0x02, 0x51, 0x21], 2);
+    assert_eq!(cpu.registers()[1], 0x02);
+    assert_eq!(cpu.pc(), 0x206);
+    assert_ne!(cpu.decode(0x5121), Instruction::Custom(0x5121));
+    assert_eq!(cpu.decode(0x5122), Instruction::SaveRange(1, 2));
+}
+
+#[test]
+fn extension_writes_reach_display_memory() {
+    // V0 = 0x80, I = 0x100 (top left of the DREAM 6800 display), 0080
+    let cpu = with_five(
+        CPUVariant::Dream6800,
+        &[0x60, 0x80, 0xA1, 0x00, 0x00, 0x80],
+        3,
+    );
+    assert!(cpu.display().pixel(0, 0));
+    assert!(!cpu.display().pixel(1, 0));
+}
+
+// Runs `code` with I pointing at `sprite`, which goes right after it
+fn draw(variant: CPUVariant, code: &[u8], sprite: &[u8]) -> CPU {
+    let sprite_address = 0x200 + 2 + code.len();
+    let mut rom = vec![0xA0 | (sprite_address >> 8) as u8, sprite_address as u8];
+    rom.extend(code);
+    rom.extend(sprite);
+    run(variant, QuirkOverrides::default(), &rom, 1 + code.len() / 2)
+}
+
+// Lit display pixels in a row
+fn lit(cpu: &CPU, y: usize) -> Vec<usize> {
+    (0..cpu.display().width())
+        .filter(|&x| cpu.display().pixel(x, y))
+        .collect()
+}
+
+#[test]
+fn sprite_straddles_words() {
+    // Hires, V0 = 60, draw 8 pixels
+    let cpu = draw(
+        CPUVariant::SChipModern,
+        &[0x00, 0xFF, 0x60, 0x3C, 0xD0, 0x11],
+        &[0xFF],
+    );
+    assert_eq!(lit(&cpu, 0), (60..68).collect::<Vec<_>>());
+
+    // Lores doubles it, V0 = 30 covers display pixels 60-75
+    let cpu = draw(CPUVariant::SChipModern, &[0x60, 0x1E, 0xD0, 0x11], &[0xA5]);
+    let expected = [60, 61, 64, 65, 70, 71, 74, 75];
+    assert_eq!(lit(&cpu, 0), expected);
+    assert_eq!(lit(&cpu, 1), expected);
+    assert!(lit(&cpu, 2).is_empty());
+}
+
+#[test]
+fn sprite_clips_at_right_edge() {
+    // Lores CHIP-8, V0 = 60
+    let cpu = draw(CPUVariant::Chip8, &[0x60, 0x3C, 0xD0, 0x11], &[0xFF]);
+    assert_eq!(lit(&cpu, 0), [60, 61, 62, 63]);
+
+    // Hires SCHIP, V0 = 124
+    let cpu = draw(
+        CPUVariant::SChipModern,
+        &[0x00, 0xFF, 0x60, 0x7C, 0xD0, 0x11],
+        &[0xFF],
+    );
+    assert_eq!(lit(&cpu, 0), [124, 125, 126, 127]);
+
+    // Bottom edge too, V1 = 31 with two rows
+    let cpu = draw(CPUVariant::Chip8, &[0x61, 0x1F, 0xD0, 0x12], &[0x80, 0x80]);
+    assert_eq!(lit(&cpu, 31), [0]);
+    assert!(lit(&cpu, 0).is_empty());
+}
+
+#[test]
+fn sprite_wraps_at_right_edge() {
+    // Lores XO-CHIP, V0 = 60: lores pixels 60-63 then 0-3, doubled
+    let cpu = draw(CPUVariant::XOChip, &[0x60, 0x3C, 0xD0, 0x11], &[0xFF]);
+    let expected: Vec<usize> = (0..8).chain(120..128).collect();
+    assert_eq!(lit(&cpu, 0), expected);
+
+    // Hires XO-CHIP, V0 = 124
+    let cpu = draw(
+        CPUVariant::XOChip,
+        &[0x00, 0xFF, 0x60, 0x7C, 0xD0, 0x11],
+        &[0xFF],
+    );
+    assert_eq!(lit(&cpu, 0), [0, 1, 2, 3, 124, 125, 126, 127]);
+}
+
+#[test]
+fn collisions_set_vf() {
+    // Draw, then draw overlapping by one pixel, then somewhere empty
+    let code = [
+        0xD0, 0x11, 0x60, 0x07, 0xD0, 0x11, 0x8E, 0xF0, 0x60, 0x20, 0xD0, 0x11,
+    ];
+    let cpu = draw(CPUVariant::Chip8, &code, &[0xFF]);
+    assert_eq!(cpu.registers()[0xE], 1);
+    assert_eq!(cpu.registers()[0xF], 0);
+    assert_eq!(
+        lit(&cpu, 0),
+        [0, 1, 2, 3, 4, 5, 6, 8, 9, 10, 11, 12, 13, 14, 32, 33, 34, 35, 36, 37, 38, 39]
+    );
+}
+
+#[test]
+fn schip_scrolls() {
+    // Hires, draw 8 pixels at 62, scroll right 4 (over the word boundary), then left 4
+    let code = [0x00, 0xFF, 0x60, 0x3E, 0xD0, 0x11, 0x00, 0xFB];
+    let cpu = draw(CPUVariant::SChipModern, &code, &[0xFF]);
+    assert_eq!(lit(&cpu, 0), (66..74).collect::<Vec<_>>());
+
+    let code = [0x00, 0xFF, 0x60, 0x3E, 0xD0, 0x11, 0x00, 0xFC];
+    let cpu = draw(CPUVariant::SChipModern, &code, &[0xFF]);
+    assert_eq!(lit(&cpu, 0), (58..66).collect::<Vec<_>>());
+
+    // Pushed off the right edge and gone, scrolling back doesn't bring it back
+    let code = [0x00, 0xFF, 0x60, 0x7C, 0xD0, 0x11, 0x00, 0xFB, 0x00, 0xFC];
+    let cpu = draw(CPUVariant::SChipModern, &code, &[0xFF]);
+    assert!(lit(&cpu, 0).is_empty());
+
+    // Lores scrolls whole lores pixels with the scroll quirk, half of one without
+    let code = [0xD0, 0x11, 0x00, 0xFB];
+    let cpu = draw(CPUVariant::SChipModern, &code, &[0x80]);
+    assert_eq!(lit(&cpu, 0), [8, 9]);
+    let cpu = draw(CPUVariant::SChipv1_1, &code, &[0x80]);
+    assert_eq!(lit(&cpu, 0), [4, 5]);
+
+    // Hires, scroll down 3
+    let code = [0x00, 0xFF, 0xD0, 0x11, 0x00, 0xC3];
+    let cpu = draw(CPUVariant::SChipModern, &code, &[0x80]);
+    assert!(lit(&cpu, 0).is_empty());
+    assert_eq!(lit(&cpu, 3), [0]);
+}
@@ -0,0 +1,332 @@
+// ROM database lookups, keyed by the SHA-1 of the ROM contents
+// The JSON layout follows programs.json from the community CHIP-8 database:
+// https://github.com/chip-8/chip-8-database
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    cpu::{CPUVariant, FontSet, QuirkOverrides},
+    input::{ControlKey, InputKey},
+    video::{palette::Palette, Rgb},
+};
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkEntry>,
+    tickrate: Option<usize>,
+    colors: Option<ColorEntry>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    font_style: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkEntry {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    wrap: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ColorEntry {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+// Everything the emulator needs to know about a ROM found in the database
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub variant: CPUVariant,
+    pub quirks: QuirkOverrides,
+    pub tickrate: Option<usize>,
+    pub colors: Option<Palette>,
+    pub keys: Vec<(ControlKey, InputKey)>,
+    pub font: Option<FontSet>,
+}
+
+impl RomInfo {
+    // Window title, e.g. "Tetris - Fran Dachille"
+    pub fn display_title(&self) -> String {
+        match self.authors.is_empty() {
+            true => self.title.clone(),
+            false => format!("{} - {}", self.title, self.authors.join(", ")),
+        }
+    }
+}
+
+pub struct Database {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>, // ROM hash -> index into programs
+}
+
+impl Database {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let programs: Vec<Program> = serde_json::from_str(json)?;
+
+        let hashes = programs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, program)| program.roms.keys().map(move |hash| (hash.clone(), i)))
+            .collect();
+
+        Ok(Self { programs, hashes })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        let hash = hash_rom(rom);
+        let program = &self.programs[*self.hashes.get(&hash)?];
+        let entry = &program.roms[&hash];
+
+        // Pick the first platform we can emulate, the database lists them by preference
+        let (platform, variant) = entry
+            .platforms
+            .iter()
+            .find_map(|platform| platform_variant(platform).map(|v| (platform, v)))?;
+
+        let mut quirks = platform_quirks(platform);
+        if let Some(quirk_entry) = entry.quirky_platforms.get(platform) {
+            quirk_entry.apply(&mut quirks);
+        }
+
+        // Background, then up to three pixel colours for XO-CHIP planes
+        let colors = entry.colors.as_ref().and_then(|colors| {
+            let pixels = colors
+                .pixels
+                .iter()
+                .map(|pixel| Rgb::from_hex(pixel))
+                .collect::<Option<Vec<_>>>()?;
+            Palette::from_colors(&pixels)
+        });
+
+        let keys = entry
+            .keys
+            .iter()
+            .filter_map(|(control, &key)| Some((control_key(control)?, hex_key(key)?)))
+            .collect();
+
+        Some(RomInfo {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            variant,
+            quirks,
+            tickrate: entry.tickrate,
+            colors,
+            keys,
+            font: entry.font_style.as_deref().and_then(FontSet::from_name),
+        })
+    }
+}
+
+impl QuirkEntry {
+    fn apply(&self, quirks: &mut QuirkOverrides) {
+        if self.shift.is_some() {
+            quirks.shift_quirk = self.shift;
+        }
+        if self.jump.is_some() {
+            quirks.jump_quirk = self.jump;
+        }
+        if self.vblank.is_some() {
+            quirks.vblank_quirk = self.vblank;
+        }
+        if self.wrap.is_some() {
+            quirks.wrap_quirk = self.wrap;
+        }
+        if self.logic.is_some() {
+            quirks.logic_quirk = self.logic;
+        }
+
+        // Leaving I unchanged takes priority over incrementing by X, the VIP's X + 1 only
+        // when the entry rules out both. A lone false says nothing about the offset
+        match (self.memory_leave_i_unchanged, self.memory_increment_by_x) {
+            (Some(true), _) => quirks.load_store_offset = Some(None),
+            (_, Some(true)) => quirks.load_store_offset = Some(Some(0)),
+            (Some(false), Some(false)) => quirks.load_store_offset = Some(Some(1)),
+            _ => (),
+        }
+    }
+}
+
+// Lowercase hex SHA-1, as used for database keys
+pub fn hash_rom(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+// Guess the variant from a ROM's file extension
+pub fn variant_from_extension<P: AsRef<Path>>(path: P) -> Option<CPUVariant> {
+    let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "ch8" => Some(CPUVariant::Chip8),
+        "sc8" => Some(CPUVariant::SChipv1_1),
+        "xo8" => Some(CPUVariant::XOChip),
+        _ => None,
+    }
+}
+
+fn platform_variant(platform: &str) -> Option<CPUVariant> {
+    match platform {
+        "originalChip8" | "hybridVIP" | "modernChip8" => Some(CPUVariant::Chip8),
+        "chip48" => Some(CPUVariant::Chip48),
+        "superchip1" => Some(CPUVariant::SChipv1_0),
+        "superchip" => Some(CPUVariant::SChipv1_1),
+        "xochip" => Some(CPUVariant::XOChip),
+        "chip8x" => Some(CPUVariant::Chip8X),
+        _ => None,
+    }
+}
+
+// Platforms that share a variant but differ in quirks
+fn platform_quirks(platform: &str) -> QuirkOverrides {
+    match platform {
+        "modernChip8" => QuirkOverrides {
+            logic_quirk: Some(false),
+            vblank_quirk: Some(false),
+            ..Default::default()
+        },
+        _ => QuirkOverrides::default(),
+    }
+}
+
+fn control_key(control: &str) -> Option<ControlKey> {
+    match control {
+        "up" => Some(ControlKey::Up),
+        "down" => Some(ControlKey::Down),
+        "left" => Some(ControlKey::Left),
+        "right" => Some(ControlKey::Right),
+        "a" => Some(ControlKey::A),
+        "b" => Some(ControlKey::B),
+        _ => None,
+    }
+}
+
+fn hex_key(key: u8) -> Option<InputKey> {
+    const KEYS: [InputKey; 16] = [
+        InputKey::K0,
+        InputKey::K1,
+        InputKey::K2,
+        InputKey::K3,
+        InputKey::K4,
+        InputKey::K5,
+        InputKey::K6,
+        InputKey::K7,
+        InputKey::K8,
+        InputKey::K9,
+        InputKey::KA,
+        InputKey::KB,
+        InputKey::KC,
+        InputKey::KD,
+        InputKey::KE,
+        InputKey::KF,
+    ];
+
+    KEYS.get(key as usize).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = &[0x00, 0xE0, 0x12, 0x00];
+
+    fn database(rom_entry: &str) -> Database {
+        let json = format!(
+            r#"[{{"title": "Test", "authors": ["Someone"], "roms": {{"{}": {}}}}}]"#,
+            hash_rom(ROM),
+            rom_entry
+        );
+        Database::from_json(&json).unwrap()
+    }
+
+    fn offset(memory: &str) -> Option<Option<usize>> {
+        let entry = format!(
+            r#"{{"platforms": ["superchip"], "quirkyPlatforms": {{"superchip": {{{}}}}}}}"#,
+            memory
+        );
+        database(&entry)
+            .lookup(ROM)
+            .unwrap()
+            .quirks
+            .load_store_offset
+    }
+
+    #[test]
+    fn looks_up_by_sha1() {
+        let database = database(
+            r##"{"platforms": ["unknownPlatform", "chip48"], "tickrate": 30,
+                "colors": {"pixels": ["#000000", "#FF0000", "#00FF00", "#0000FF"]},
+                "keys": {"up": 5}}"##,
+        );
+
+        let info = database.lookup(ROM).unwrap();
+        assert_eq!(info.display_title(), "Test - Someone");
+        assert_eq!(info.variant, CPUVariant::Chip48);
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(
+            info.colors.unwrap().0,
+            [
+                Rgb(0, 0, 0),
+                Rgb(0xFF, 0, 0),
+                Rgb(0, 0xFF, 0),
+                Rgb(0, 0, 0xFF)
+            ]
+        );
+        assert_eq!(info.keys, vec![(ControlKey::Up, InputKey::K5)]);
+
+        assert!(database.lookup(&[0x00, 0xE0]).is_none());
+    }
+
+    #[test]
+    fn maps_quirks() {
+        let database = database(
+            r#"{"platforms": ["modernChip8"],
+                "quirkyPlatforms": {"modernChip8": {"shift": true, "wrap": false}}}"#,
+        );
+
+        let quirks = database.lookup(ROM).unwrap().quirks;
+        assert_eq!(quirks.shift_quirk, Some(true));
+        assert_eq!(quirks.wrap_quirk, Some(false));
+        // From the platform itself
+        assert_eq!(quirks.logic_quirk, Some(false));
+        assert_eq!(quirks.vblank_quirk, Some(false));
+        assert_eq!(quirks.jump_quirk, None);
+    }
+
+    #[test]
+    fn maps_load_store_offset() {
+        assert_eq!(offset(r#""memoryLeaveIUnchanged": true"#), Some(None));
+        assert_eq!(offset(r#""memoryIncrementByX": true"#), Some(Some(0)));
+        assert_eq!(
+            offset(r#""memoryLeaveIUnchanged": false, "memoryIncrementByX": false"#),
+            Some(Some(1))
+        );
+        // Keeps the variant's default
+        assert_eq!(offset(r#""memoryLeaveIUnchanged": false"#), None);
+        assert_eq!(offset(r#""memoryIncrementByX": false"#), None);
+        assert_eq!(offset(""), None);
+    }
+}
@@ -1,5 +1,8 @@
+pub mod script_input;
 pub mod sdl_input;
+pub mod terminal_input;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum InputKey {
     K0 = 0x0,
     K1 = 0x1,
@@ -17,14 +20,80 @@ pub enum InputKey {
     KD = 0xD,
     KE = 0xE,
     KF = 0xF,
+    // Second keypad (CHIP-8X)
+    P2K0 = 0x10,
+    P2K1 = 0x11,
+    P2K2 = 0x12,
+    P2K3 = 0x13,
+    P2K4 = 0x14,
+    P2K5 = 0x15,
+    P2K6 = 0x16,
+    P2K7 = 0x17,
+    P2K8 = 0x18,
+    P2K9 = 0x19,
+    P2KA = 0x1A,
+    P2KB = 0x1B,
+    P2KC = 0x1C,
+    P2KD = 0x1D,
+    P2KE = 0x1E,
+    P2KF = 0x1F,
     Quit = 0x80,
+    Pause = 0x81,      // Pause/resume the CPU, for debug hooks
+    Palette = 0x82,    // Switch to the next display palette
+    Screenshot = 0x83, // Save the screen as a PNG
+    Record = 0x84,     // Start/stop recording a GIF
+}
+
+impl InputKey {
+    // A hex digit for the keypad, or one of the emulator's own keys
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "0" => Some(Self::K0),
+            "1" => Some(Self::K1),
+            "2" => Some(Self::K2),
+            "3" => Some(Self::K3),
+            "4" => Some(Self::K4),
+            "5" => Some(Self::K5),
+            "6" => Some(Self::K6),
+            "7" => Some(Self::K7),
+            "8" => Some(Self::K8),
+            "9" => Some(Self::K9),
+            "a" => Some(Self::KA),
+            "b" => Some(Self::KB),
+            "c" => Some(Self::KC),
+            "d" => Some(Self::KD),
+            "e" => Some(Self::KE),
+            "f" => Some(Self::KF),
+            "quit" => Some(Self::Quit),
+            "pause" => Some(Self::Pause),
+            "palette" => Some(Self::Palette),
+            "screenshot" => Some(Self::Screenshot),
+            "record" => Some(Self::Record),
+            _ => None,
+        }
+    }
 }
 
 pub enum InputEvent {
     KeyPressed(InputKey),
     KeyReleased(InputKey),
+    WindowChanged, // Resized or uncovered, the frame has to be presented again
+}
+
+// Directional/action controls that a ROM can bind to CHIP-8 keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
 }
 
 pub trait Input {
     fn poll_input(&mut self) -> Option<InputEvent>;
+
+    // Map a control to a CHIP-8 key, on top of the default hex keypad
+    fn bind_control(&mut self, _control: ControlKey, _key: InputKey) {}
 }
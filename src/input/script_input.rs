@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+use super::{Input, InputEvent, InputKey};
+
+// Replays a script of input events, for tests and headless runs
+// Frontends poll once a frame, so each poll counts as a frame. Events due on the same frame
+// come out on consecutive polls
+#[derive(Default)]
+pub struct ScriptInput {
+    frame: u64,
+    events: VecDeque<(u64, InputEvent)>, // Frame each event is due, in order
+}
+
+impl ScriptInput {
+    pub fn new<I: IntoIterator<Item = (u64, InputEvent)>>(script: I) -> Self {
+        let mut events: Vec<_> = script.into_iter().collect();
+        events.sort_by_key(|&(frame, _)| frame);
+
+        Self {
+            frame: 0,
+            events: events.into(),
+        }
+    }
+
+    // One event per line, "<frame> <press|release> <key>" where the key is a hex digit or
+    // quit, pause, palette, screenshot or record. Blank lines and lines starting with # are skipped
+    pub fn parse(script: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut events = vec![];
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = || format!("bad input on line {}: {}", number + 1, line);
+            let [frame, action, key] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(bad_line().into());
+            };
+
+            let frame = frame.parse::<u64>().map_err(|_| bad_line())?;
+            let key = InputKey::from_name(key).ok_or_else(bad_line)?;
+            let event = match action {
+                "press" => InputEvent::KeyPressed(key),
+                "release" => InputEvent::KeyReleased(key),
+                _ => return Err(bad_line().into()),
+            };
+            events.push((frame, event));
+        }
+        Ok(Self::new(events))
+    }
+
+    // Nothing left to replay
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl Input for ScriptInput {
+    fn poll_input(&mut self) -> Option<InputEvent> {
+        let event = match self.events.front() {
+            Some(&(frame, _)) if frame <= self.frame => self.events.pop_front().map(|(_, e)| e),
+            _ => None,
+        };
+        self.frame += 1;
+        event
+    }
+}
@@ -1,16 +1,26 @@
-use sdl2::{event::Event, keyboard::Keycode, EventPump, Sdl};
+use std::collections::HashMap;
 
-use super::{Input, InputEvent, InputKey};
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    EventPump, Sdl,
+};
+
+use super::{ControlKey, Input, InputEvent, InputKey};
 
 pub struct SDLInput {
     event_pump: EventPump,
+    controls: HashMap<Keycode, InputKey>,
 }
 
 impl SDLInput {
     pub fn new(sdl_context: &Sdl) -> Result<Self, Box<dyn std::error::Error>> {
         let event_pump = sdl_context.event_pump()?;
 
-        Ok(Self { event_pump })
+        Ok(Self {
+            event_pump,
+            controls: HashMap::new(),
+        })
     }
 }
 
@@ -21,11 +31,31 @@ impl Input for SDLInput {
 
             let input = match event {
                 Event::Quit { .. } => Some(InputEvent::KeyPressed(InputKey::Quit)),
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => Some(InputEvent::WindowChanged),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if self.controls.contains_key(&keycode) => {
+                    Some(InputEvent::KeyPressed(self.controls[&keycode]))
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } if self.controls.contains_key(&keycode) => {
+                    Some(InputEvent::KeyReleased(self.controls[&keycode]))
+                }
                 Event::KeyDown {
                     keycode: Some(keycode),
                     ..
                 } => match keycode {
                     Keycode::Escape => Some(InputEvent::KeyPressed(InputKey::Quit)),
+                    Keycode::P | Keycode::Pause => Some(InputEvent::KeyPressed(InputKey::Pause)),
+                    Keycode::Tab => Some(InputEvent::KeyPressed(InputKey::Palette)),
+                    Keycode::F5 => Some(InputEvent::KeyPressed(InputKey::Screenshot)),
+                    Keycode::F6 => Some(InputEvent::KeyPressed(InputKey::Record)),
                     Keycode::Num0 => Some(InputEvent::KeyPressed(InputKey::K0)),
                     Keycode::Num1 => Some(InputEvent::KeyPressed(InputKey::K1)),
                     Keycode::Num2 => Some(InputEvent::KeyPressed(InputKey::K2)),
@@ -42,6 +72,23 @@ impl Input for SDLInput {
                     Keycode::D => Some(InputEvent::KeyPressed(InputKey::KD)),
                     Keycode::E => Some(InputEvent::KeyPressed(InputKey::KE)),
                     Keycode::F => Some(InputEvent::KeyPressed(InputKey::KF)),
+                    // Second keypad on the numpad
+                    Keycode::Kp0 => Some(InputEvent::KeyPressed(InputKey::P2K0)),
+                    Keycode::Kp1 => Some(InputEvent::KeyPressed(InputKey::P2K1)),
+                    Keycode::Kp2 => Some(InputEvent::KeyPressed(InputKey::P2K2)),
+                    Keycode::Kp3 => Some(InputEvent::KeyPressed(InputKey::P2K3)),
+                    Keycode::Kp4 => Some(InputEvent::KeyPressed(InputKey::P2K4)),
+                    Keycode::Kp5 => Some(InputEvent::KeyPressed(InputKey::P2K5)),
+                    Keycode::Kp6 => Some(InputEvent::KeyPressed(InputKey::P2K6)),
+                    Keycode::Kp7 => Some(InputEvent::KeyPressed(InputKey::P2K7)),
+                    Keycode::Kp8 => Some(InputEvent::KeyPressed(InputKey::P2K8)),
+                    Keycode::Kp9 => Some(InputEvent::KeyPressed(InputKey::P2K9)),
+                    Keycode::KpDivide => Some(InputEvent::KeyPressed(InputKey::P2KA)),
+                    Keycode::KpMultiply => Some(InputEvent::KeyPressed(InputKey::P2KB)),
+                    Keycode::KpMinus => Some(InputEvent::KeyPressed(InputKey::P2KC)),
+                    Keycode::KpPlus => Some(InputEvent::KeyPressed(InputKey::P2KD)),
+                    Keycode::KpEnter => Some(InputEvent::KeyPressed(InputKey::P2KE)),
+                    Keycode::KpPeriod => Some(InputEvent::KeyPressed(InputKey::P2KF)),
                     _ => None,
                 },
                 Event::KeyUp {
@@ -64,6 +111,23 @@ impl Input for SDLInput {
                     Keycode::D => Some(InputEvent::KeyReleased(InputKey::KD)),
                     Keycode::E => Some(InputEvent::KeyReleased(InputKey::KE)),
                     Keycode::F => Some(InputEvent::KeyReleased(InputKey::KF)),
+                    // Second keypad on the numpad
+                    Keycode::Kp0 => Some(InputEvent::KeyReleased(InputKey::P2K0)),
+                    Keycode::Kp1 => Some(InputEvent::KeyReleased(InputKey::P2K1)),
+                    Keycode::Kp2 => Some(InputEvent::KeyReleased(InputKey::P2K2)),
+                    Keycode::Kp3 => Some(InputEvent::KeyReleased(InputKey::P2K3)),
+                    Keycode::Kp4 => Some(InputEvent::KeyReleased(InputKey::P2K4)),
+                    Keycode::Kp5 => Some(InputEvent::KeyReleased(InputKey::P2K5)),
+                    Keycode::Kp6 => Some(InputEvent::KeyReleased(InputKey::P2K6)),
+                    Keycode::Kp7 => Some(InputEvent::KeyReleased(InputKey::P2K7)),
+                    Keycode::Kp8 => Some(InputEvent::KeyReleased(InputKey::P2K8)),
+                    Keycode::Kp9 => Some(InputEvent::KeyReleased(InputKey::P2K9)),
+                    Keycode::KpDivide => Some(InputEvent::KeyReleased(InputKey::P2KA)),
+                    Keycode::KpMultiply => Some(InputEvent::KeyReleased(InputKey::P2KB)),
+                    Keycode::KpMinus => Some(InputEvent::KeyReleased(InputKey::P2KC)),
+                    Keycode::KpPlus => Some(InputEvent::KeyReleased(InputKey::P2KD)),
+                    Keycode::KpEnter => Some(InputEvent::KeyReleased(InputKey::P2KE)),
+                    Keycode::KpPeriod => Some(InputEvent::KeyReleased(InputKey::P2KF)),
                     _ => None,
                 },
                 _ => continue,
@@ -76,4 +140,17 @@ impl Input for SDLInput {
 
         None
     }
+
+    fn bind_control(&mut self, control: ControlKey, key: InputKey) {
+        let keycode = match control {
+            ControlKey::Up => Keycode::Up,
+            ControlKey::Down => Keycode::Down,
+            ControlKey::Left => Keycode::Left,
+            ControlKey::Right => Keycode::Right,
+            ControlKey::A => Keycode::Space,
+            ControlKey::B => Keycode::LShift,
+        };
+
+        self.controls.insert(keycode, key);
+    }
 }
@@ -0,0 +1,170 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::stdout,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, terminal,
+};
+
+use super::{ControlKey, Input, InputEvent, InputKey};
+
+// Reads keys from the terminal in raw mode. Most terminals only send presses (repeated while
+// held), so a key counts as held until it hasn't been sent for `hold_time`. Terminals with the
+// kitty keyboard protocol report releases, and keys are held exactly as long as they're down
+pub struct TerminalInput {
+    controls: HashMap<KeyCode, InputKey>,
+    hold_time: Duration,
+    held: HashMap<InputKey, Instant>, // Keys down, and when the terminal last sent them
+    events: VecDeque<InputEvent>,
+    releases: bool, // The terminal reports key releases
+}
+
+impl TerminalInput {
+    // Puts the terminal in raw mode until dropped
+    pub fn new(hold_time: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        terminal::enable_raw_mode()?;
+
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(
+                stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(Self {
+            controls: HashMap::new(),
+            hold_time,
+            held: HashMap::new(),
+            events: VecDeque::new(),
+            releases,
+        })
+    }
+
+    fn handle_key(&mut self, event: KeyEvent) {
+        let Some(key) = self.input_key(event) else {
+            return;
+        };
+
+        match event.kind {
+            KeyEventKind::Release => {
+                if self.held.remove(&key).is_some() {
+                    self.events.push_back(InputEvent::KeyReleased(key));
+                }
+            }
+            // Emulator controls act once, repeats don't toggle them again
+            _ if key as u8 >= InputKey::Quit as u8 => {
+                if event.kind == KeyEventKind::Press {
+                    self.events.push_back(InputEvent::KeyPressed(key));
+                }
+            }
+            // Presses and repeats keep the key held
+            _ => {
+                if self.held.insert(key, Instant::now()).is_none() {
+                    self.events.push_back(InputEvent::KeyPressed(key));
+                }
+            }
+        }
+    }
+
+    fn input_key(&self, event: KeyEvent) -> Option<InputKey> {
+        // Raw mode turns Ctrl+C into a key like any other
+        if event.modifiers.contains(KeyModifiers::CONTROL) {
+            return match event.code {
+                KeyCode::Char('c') => Some(InputKey::Quit),
+                _ => None,
+            };
+        }
+
+        if let Some(&key) = self.controls.get(&event.code) {
+            return Some(key);
+        }
+
+        // No numpad keys, terminals send the same codes as the main keys
+        match event.code {
+            KeyCode::Esc => Some(InputKey::Quit),
+            KeyCode::Char('p') | KeyCode::Char('P') => Some(InputKey::Pause),
+            KeyCode::Tab => Some(InputKey::Palette),
+            KeyCode::F(5) => Some(InputKey::Screenshot),
+            KeyCode::F(6) => Some(InputKey::Record),
+            KeyCode::Char(c) => match c.to_ascii_lowercase() {
+                '0' => Some(InputKey::K0),
+                '1' => Some(InputKey::K1),
+                '2' => Some(InputKey::K2),
+                '3' => Some(InputKey::K3),
+                '4' => Some(InputKey::K4),
+                '5' => Some(InputKey::K5),
+                '6' => Some(InputKey::K6),
+                '7' => Some(InputKey::K7),
+                '8' => Some(InputKey::K8),
+                '9' => Some(InputKey::K9),
+                'a' => Some(InputKey::KA),
+                'b' => Some(InputKey::KB),
+                'c' => Some(InputKey::KC),
+                'd' => Some(InputKey::KD),
+                'e' => Some(InputKey::KE),
+                'f' => Some(InputKey::KF),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Input for TerminalInput {
+    fn poll_input(&mut self) -> Option<InputEvent> {
+        // Read errors are treated like no input
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            match event::read() {
+                Ok(Event::Key(event)) => self.handle_key(event),
+                Ok(Event::Resize(..)) => self.events.push_back(InputEvent::WindowChanged),
+                _ => (),
+            }
+        }
+
+        // Keys the terminal stopped repeating have been let go
+        if !self.releases {
+            let now = Instant::now();
+            let released: Vec<InputKey> = self
+                .held
+                .iter()
+                .filter(|(_, &sent)| now.duration_since(sent) >= self.hold_time)
+                .map(|(&key, _)| key)
+                .collect();
+            for key in released {
+                self.held.remove(&key);
+                self.events.push_back(InputEvent::KeyReleased(key));
+            }
+        }
+
+        self.events.pop_front()
+    }
+
+    fn bind_control(&mut self, control: ControlKey, key: InputKey) {
+        // Shift on its own never reaches the terminal, B goes on Enter instead
+        let code = match control {
+            ControlKey::Up => KeyCode::Up,
+            ControlKey::Down => KeyCode::Down,
+            ControlKey::Left => KeyCode::Left,
+            ControlKey::Right => KeyCode::Right,
+            ControlKey::A => KeyCode::Char(' '),
+            ControlKey::B => KeyCode::Enter,
+        };
+        self.controls.insert(code, key);
+    }
+}
+
+impl Drop for TerminalInput {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -5,7 +5,15 @@ pub const SCREEN_HEIGHT: usize = 32;
 pub const MAX_RESOLUTION_WIDTH: usize = 256;
 pub const MAX_RESOLUTION_HEIGHT: usize = 196;
 
+// Emulated frames per second, timers and recordings run at this rate
+pub const FRAME_RATE: u32 = 60;
+
+pub mod analysis;
+pub mod coverage;
 pub mod cpu;
+pub mod database;
+pub mod lint;
+pub mod profiler;
 
 // Modules for other parts of emulator
 pub mod audio;
@@ -0,0 +1,302 @@
+// Runtime checks for likely ROM bugs, built on the CPU's observer hooks
+// Static checks live in analysis, these catch what only shows up while running
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    ops::Range,
+};
+
+use crate::cpu::{Draw, Instruction, Observer, CPU, LOAD_STORE_LOOKAHEAD};
+
+// The COSMAC VIP only has room for 12 return addresses
+pub const DEFAULT_CALL_DEPTH: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LintKind {
+    StackUnderflow,    // 00EE with nothing to return to
+    CallDepth,         // Calls nested deeper than the limit
+    UninitializedRead, // Memory read that isn't part of the ROM or font and was never written
+    FontWrite,         // Write into the font
+    SelfModifyingCode, // Write over code that has run, or running code that was written
+    OffscreenSprite,   // DXYN with VX/VY past the edge of the screen
+    KeyWaitWhileHeld,  // FX0A while a key is still down
+    ShiftQuirk,        // 8XY6/8XYE with X != Y
+    JumpQuirk,         // BNNN with VX != V0
+    LoadStoreQuirk,    // FX55/FX65 followed by code that uses I
+    LogicQuirk,        // 8XY1-8XY3 involving VF
+    FlagOrderQuirk,    // 8XY4-8XYE with X = F
+    IOverflowQuirk,    // FX1E taking I past 0xFFF
+    WrapQuirk,         // Sprite partly off the edge, clipped or wrapped depending on the platform
+    SoundTimerQuirk,   // FX18 with VX = 1, the VIP ignores it
+}
+
+impl LintKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LintKind::StackUnderflow => "return with an empty stack",
+            LintKind::CallDepth => "calls nested deeper than the stack limit",
+            LintKind::UninitializedRead => "read from memory that was never initialised",
+            LintKind::FontWrite => "write into the font",
+            LintKind::SelfModifyingCode => "self-modifying code",
+            LintKind::OffscreenSprite => "sprite drawn off-screen, the position wraps around",
+            LintKind::KeyWaitWhileHeld => "waiting for a key while one is still held",
+            LintKind::ShiftQuirk => "shift result depends on the shift quirk",
+            LintKind::JumpQuirk => "jump target depends on the jump quirk",
+            LintKind::LoadStoreQuirk => "I after save/load depends on the load/store quirk",
+            LintKind::LogicQuirk => "VF after a logic op depends on the logic quirk",
+            LintKind::FlagOrderQuirk => "VF as the destination, result depends on flag order",
+            LintKind::IOverflowQuirk => "I overflow sets VF on some platforms",
+            LintKind::WrapQuirk => "sprite crosses the screen edge, clipped or wrapped by platform",
+            LintKind::SoundTimerQuirk => "buzzer := 1 is ignored on the VIP",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LintFinding {
+    pub kind: LintKind,
+    pub pc: usize,
+    pub opcode: u16,
+    pub frame: usize,           // First frame it happened in
+    pub address: Option<usize>, // Memory address involved, if any
+    pub count: usize,           // Times it happened at this PC
+}
+
+pub struct Linter {
+    max_call_depth: usize,
+    initialized: Vec<bool>,
+    executed: HashSet<usize>,
+    written: HashSet<usize>, // Written at runtime
+    font: Range<usize>,
+
+    frame: usize,
+    pc: usize,
+    opcode: u16,
+    depth: usize,
+    index: usize,                            // I before the current instruction
+    load_store: Option<(usize, u16, usize)>, // (pc, opcode, instructions left) of the last FX55/FX65
+
+    findings: Vec<LintFinding>,
+    seen: HashMap<(LintKind, usize), usize>, // (kind, pc) -> index into findings
+}
+
+impl Linter {
+    // Should be created after the ROM is loaded, before it starts running
+    pub fn new(cpu: &CPU, rom_len: usize) -> Self {
+        let rom = cpu.pc()..cpu.pc() + rom_len;
+        let font = cpu.font_range();
+
+        let mut initialized = vec![false; rom.end.max(font.end)];
+        for address in rom.chain(font.clone()) {
+            initialized[address] = true;
+        }
+
+        Self {
+            max_call_depth: DEFAULT_CALL_DEPTH,
+            initialized,
+            executed: HashSet::new(),
+            written: HashSet::new(),
+            font,
+            frame: 0,
+            pc: 0,
+            opcode: 0,
+            depth: 0,
+            index: 0,
+            load_store: None,
+            findings: vec![],
+            seen: HashMap::new(),
+        }
+    }
+
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    pub fn findings(&self) -> &[LintFinding] {
+        &self.findings
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for finding in &self.findings {
+            write!(
+                report,
+                "frame {:>6}  {:#05X}  {:04X}  {}",
+                finding.frame,
+                finding.pc,
+                finding.opcode,
+                finding.kind.describe()
+            )
+            .unwrap();
+            if let Some(address) = finding.address {
+                write!(report, " ({:#05X})", address).unwrap();
+            }
+            if finding.count > 1 {
+                write!(report, " x{}", finding.count).unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+        report
+    }
+
+    fn flag_at(&mut self, kind: LintKind, pc: usize, opcode: u16, address: Option<usize>) {
+        match self.seen.get(&(kind, pc)) {
+            Some(&index) => self.findings[index].count += 1,
+            None => {
+                self.seen.insert((kind, pc), self.findings.len());
+                self.findings.push(LintFinding {
+                    kind,
+                    pc,
+                    opcode,
+                    frame: self.frame,
+                    address,
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    fn flag(&mut self, kind: LintKind, address: Option<usize>) {
+        self.flag_at(kind, self.pc, self.opcode, address);
+    }
+
+    fn is_initialized(&self, address: usize) -> bool {
+        self.initialized.get(address).copied().unwrap_or(false)
+    }
+
+    // Quirks that can be spotted from the instruction alone
+    fn check_quirks(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::ShiftRight(x, y) | Instruction::ShiftLeft(x, y) if x != y => {
+                self.flag(LintKind::ShiftQuirk, None)
+            }
+            Instruction::Or(x, y) | Instruction::And(x, y) | Instruction::Xor(x, y)
+                if x == 0xF || y == 0xF =>
+            {
+                self.flag(LintKind::LogicQuirk, None)
+            }
+            Instruction::Add(0xF, _)
+            | Instruction::Sub(0xF, _)
+            | Instruction::ShiftRight(0xF, _)
+            | Instruction::SubReverse(0xF, _)
+            | Instruction::ShiftLeft(0xF, _) => self.flag(LintKind::FlagOrderQuirk, None),
+            _ => (),
+        }
+
+        // I is only safe to use after FX55/FX65 once it's been set again
+        if let Some((pc, opcode, remaining)) = self.load_store {
+            match instruction {
+                Instruction::SetI(_)
+                | Instruction::SetILong
+                | Instruction::SetISprite(_)
+                | Instruction::SetIBigSprite(_) => self.load_store = None,
+                Instruction::Draw(..)
+                | Instruction::AddI(_)
+                | Instruction::Bcd(_)
+                | Instruction::Store(_)
+                | Instruction::Load(_)
+                | Instruction::SaveRange(..)
+                | Instruction::LoadRange(..) => {
+                    self.flag_at(LintKind::LoadStoreQuirk, pc, opcode, None);
+                    self.load_store = None;
+                }
+                _ if remaining <= 1 => self.load_store = None,
+                _ => self.load_store = Some((pc, opcode, remaining - 1)),
+            }
+        }
+        if let Instruction::Store(_) | Instruction::Load(_) = instruction {
+            self.load_store = Some((self.pc, self.opcode, LOAD_STORE_LOOKAHEAD));
+        }
+    }
+}
+
+impl Observer for Linter {
+    fn on_fetch(&mut self, pc: usize, opcode: u16) {
+        self.pc = pc;
+        self.opcode = opcode;
+
+        let instruction = Instruction::decode(opcode);
+        if instruction == Instruction::Return && self.depth == 0 {
+            self.flag(LintKind::StackUnderflow, None);
+        }
+
+        // Code that was written at runtime, e.g. by FX55
+        let size = instruction.size();
+        if let Some(address) = (pc..pc + size).find(|address| self.written.contains(address)) {
+            self.flag(LintKind::SelfModifyingCode, Some(address));
+        }
+        self.executed.extend(pc..pc + size);
+
+        self.check_quirks(instruction);
+    }
+
+    fn on_execute(&mut self, cpu: &CPU, _pc: usize, _opcode: u16) {
+        match Instruction::decode(self.opcode) {
+            // First keypad only, that's all FX0A looks at
+            Instruction::WaitKey(_) if cpu.keys() & 0xFFFF != 0 => {
+                self.flag(LintKind::KeyWaitWhileHeld, None)
+            }
+            Instruction::AddI(x) if self.index + cpu.registers()[x as usize] as usize > 0xFFF => {
+                self.flag(LintKind::IOverflowQuirk, None)
+            }
+            // Only matters when VX and V0 differ
+            Instruction::JumpOffset(nnn)
+                if cpu.registers()[nnn as usize >> 8] != cpu.registers()[0] =>
+            {
+                self.flag(LintKind::JumpQuirk, None)
+            }
+            Instruction::SetSound(x) if cpu.registers()[x as usize] == 1 => {
+                self.flag(LintKind::SoundTimerQuirk, None)
+            }
+            _ => (),
+        }
+
+        self.index = cpu.index();
+    }
+
+    fn on_memory_read(&mut self, address: usize, _value: u8, _opcode: u16) {
+        if !self.is_initialized(address) {
+            self.flag(LintKind::UninitializedRead, Some(address));
+        }
+    }
+
+    fn on_memory_write(&mut self, address: usize, _value: u8, _opcode: u16) {
+        if self.font.contains(&address) {
+            self.flag(LintKind::FontWrite, Some(address));
+        }
+        if self.executed.contains(&address) {
+            self.flag(LintKind::SelfModifyingCode, Some(address));
+        }
+
+        if address >= self.initialized.len() {
+            self.initialized.resize(address + 1, false);
+        }
+        self.initialized[address] = true;
+        self.written.insert(address);
+    }
+
+    fn on_call(&mut self, _from: usize, _to: usize) {
+        self.depth += 1;
+        if self.depth > self.max_call_depth {
+            self.flag(LintKind::CallDepth, None);
+        }
+    }
+
+    fn on_return(&mut self, _from: usize, _to: usize) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    fn on_draw(&mut self, draw: &Draw) {
+        if draw.wrapped {
+            self.flag(LintKind::OffscreenSprite, None);
+        }
+        if draw.crosses_edge {
+            self.flag(LintKind::WrapQuirk, None);
+        }
+    }
+
+    fn on_frame(&mut self) {
+        self.frame += 1;
+    }
+}
@@ -0,0 +1,239 @@
+// Profiling over a whole run, built on the CPU's observer hooks
+// Time is measured in instructions, which is what matters on a slow interpreter
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::cpu::{Draw, Instruction, Observer};
+
+// How many addresses to list in the report
+const HOT_SPOTS: usize = 20;
+
+#[derive(Clone, Copy, Default)]
+struct Subroutine {
+    calls: u64,
+    inclusive: u64, // Instructions run inside this subroutine, including its callees
+    exclusive: u64, // Instructions run directly in this subroutine
+}
+
+#[derive(Clone, Copy, Default)]
+struct Frame {
+    instructions: usize,
+    draws: usize,
+    pixels: usize, // Sprite pixels drawn, i.e. rows * width
+}
+
+pub struct Profiler {
+    budget: usize, // Instructions per frame the emulator allows
+    instructions: u64,
+    opcodes: HashMap<&'static str, u64>,
+    addresses: BTreeMap<usize, (u64, u16)>, // Address -> (count, opcode)
+    subroutines: BTreeMap<usize, Subroutine>,
+    stack: Vec<usize>, // Entry addresses of the subroutines we're in, starting with the entry point
+    folded: HashMap<Vec<usize>, u64>,
+    frames: Vec<Frame>,
+    frame: Frame,
+}
+
+impl Profiler {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            instructions: 0,
+            opcodes: HashMap::new(),
+            addresses: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            stack: Vec::new(),
+            folded: HashMap::new(),
+            frames: Vec::new(),
+            frame: Frame::default(),
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let percent = |count: u64| count as f64 * 100.0 / self.instructions.max(1) as f64;
+
+        writeln!(
+            report,
+            "{} instructions over {} frames",
+            self.instructions,
+            self.frames.len()
+        )
+        .unwrap();
+
+        if !self.frames.is_empty() {
+            let frames = self.frames.len() as f64;
+            let average = |value: fn(&Frame) -> usize| {
+                self.frames.iter().map(value).sum::<usize>() as f64 / frames
+            };
+            let peak = |value: fn(&Frame) -> usize| self.frames.iter().map(value).max().unwrap();
+
+            let used = average(|frame| frame.instructions);
+            let saturated = self
+                .frames
+                .iter()
+                .filter(|frame| frame.instructions >= self.budget)
+                .count();
+
+            writeln!(report, "\nInstructions per frame (budget {})", self.budget).unwrap();
+            writeln!(
+                report,
+                "  average {:.1} ({:.0}% of budget), peak {}, {} frames at budget ({:.0}%)",
+                used,
+                used * 100.0 / self.budget as f64,
+                peak(|frame| frame.instructions),
+                saturated,
+                saturated as f64 * 100.0 / frames
+            )
+            .unwrap();
+
+            writeln!(report, "\nSprites per frame").unwrap();
+            writeln!(
+                report,
+                "  average {:.1} draws, {:.1} pixels, peak {} draws, {} pixels",
+                average(|frame| frame.draws),
+                average(|frame| frame.pixels),
+                peak(|frame| frame.draws),
+                peak(|frame| frame.pixels)
+            )
+            .unwrap();
+        }
+
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        writeln!(report, "\nOpcodes").unwrap();
+        for (mnemonic, &count) in opcodes {
+            writeln!(
+                report,
+                "  {:>10} {:>5.1}%  {}",
+                count,
+                percent(count),
+                mnemonic
+            )
+            .unwrap();
+        }
+
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+
+        writeln!(report, "\nHot spots").unwrap();
+        for (address, &(count, opcode)) in addresses.into_iter().take(HOT_SPOTS) {
+            writeln!(
+                report,
+                "  {:#06X} {:>10} {:>5.1}%  {}",
+                address,
+                count,
+                percent(count),
+                Instruction::decode(opcode)
+            )
+            .unwrap();
+        }
+
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+
+        writeln!(report, "\nSubroutines").unwrap();
+        writeln!(
+            report,
+            "  {:6} {:>8} {:>17} {:>17}",
+            "", "calls", "inclusive", "exclusive"
+        )
+        .unwrap();
+        for (address, subroutine) in subroutines {
+            writeln!(
+                report,
+                "  {:#06X} {:>8} {:>10} {:>5.1}% {:>10} {:>5.1}%",
+                address,
+                subroutine.calls,
+                subroutine.inclusive,
+                percent(subroutine.inclusive),
+                subroutine.exclusive,
+                percent(subroutine.exclusive)
+            )
+            .unwrap();
+        }
+
+        report
+    }
+
+    // One line per call stack, e.g. "0x200;0x2A4;0x310 1234"
+    // Can be fed straight into flamegraph.pl or inferno
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<_> = self
+            .folded
+            .iter()
+            .map(|(stack, count)| {
+                let stack: Vec<_> = stack
+                    .iter()
+                    .map(|address| format!("{:#X}", address))
+                    .collect();
+                format!("{} {}\n", stack.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+impl Observer for Profiler {
+    // Counted on fetch, so a call is charged to the caller rather than the callee
+    fn on_fetch(&mut self, pc: usize, opcode: u16) {
+        if self.stack.is_empty() {
+            self.stack.push(pc);
+            self.subroutines.entry(pc).or_default().calls += 1;
+        }
+
+        self.instructions += 1;
+        self.frame.instructions += 1;
+        *self
+            .opcodes
+            .entry(Instruction::decode(opcode).mnemonic())
+            .or_default() += 1;
+
+        let address = self.addresses.entry(pc).or_default();
+        address.0 += 1;
+        address.1 = opcode;
+
+        // Recursive subroutines only count once towards their inclusive total
+        for (depth, entry) in self.stack.iter().enumerate() {
+            if !self.stack[..depth].contains(entry) {
+                self.subroutines.entry(*entry).or_default().inclusive += 1;
+            }
+        }
+        let current = *self.stack.last().unwrap();
+        self.subroutines.entry(current).or_default().exclusive += 1;
+
+        match self.folded.get_mut(&self.stack) {
+            Some(count) => *count += 1,
+            None => {
+                self.folded.insert(self.stack.clone(), 1);
+            }
+        }
+    }
+
+    fn on_call(&mut self, _from: usize, to: usize) {
+        self.stack.push(to);
+        self.subroutines.entry(to).or_default().calls += 1;
+    }
+
+    // Returning from the entry point is a ROM bug, keep attributing to it
+    fn on_return(&mut self, _from: usize, _to: usize) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    fn on_draw(&mut self, draw: &Draw) {
+        self.frame.draws += 1;
+        self.frame.pixels += draw.rows * draw.width;
+    }
+
+    fn on_frame(&mut self) {
+        self.frames.push(self.frame);
+        self.frame = Frame::default();
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{filter::Frame, Rgb};
+use crate::FRAME_RATE;
+
+// Many viewers play anything shorter than this at a tenth of a second, so shorter frames are merged
+const MIN_DELAY: u64 = 2;
+
+// NeuQuant speed for frames with more colours than a GIF palette holds, 1 is best and slowest
+const QUANTIZE_SPEED: i32 = 10;
+
+// Each pixel blown up to scale x scale, as packed RGB
+fn scaled_rgb(frame: &Frame, scale: usize) -> Vec<u8> {
+    let mut image = Vec::with_capacity(frame.pixels.len() * scale * scale * 3);
+    for row in frame.rows() {
+        for _ in 0..scale {
+            for &Rgb(r, g, b) in row {
+                for _ in 0..scale {
+                    image.extend_from_slice(&[r, g, b]);
+                }
+            }
+        }
+    }
+    image
+}
+
+pub fn save_png<P: AsRef<Path>>(
+    frame: &Frame,
+    scale: usize,
+    path: P,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        (frame.width * scale) as u32,
+        (frame.height * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(&scaled_rgb(frame, scale))?;
+    Ok(())
+}
+
+// Animated GIF of every emulated frame, a frame that looks the same as the last one only
+// makes the last one's delay longer
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    size: (usize, usize),
+    scale: usize,
+    frames: u64,                     // Emulated frames so far
+    pending: Option<(Vec<u8>, u64)>, // Frame not written yet, and the frame it started on
+}
+
+impl GifRecorder {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        scale: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            (width * scale) as u16,
+            (height * scale) as u16,
+            &[],
+        )?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            size: (width, height),
+            scale,
+            frames: 0,
+            pending: None,
+        })
+    }
+
+    // Call once per emulated frame (60 Hz), whether the screen changed or not
+    pub fn add_frame(&mut self, frame: &Frame) -> Result<(), Box<dyn std::error::Error>> {
+        if (frame.width, frame.height) != self.size {
+            return Err("frame size changed while recording".into());
+        }
+
+        let image = scaled_rgb(frame, self.scale);
+        self.pending = match self.pending.take() {
+            Some((last, start)) if last == image => Some((last, start)),
+            // Too short to show, the new frame takes its place
+            Some((_, start)) if delay(start, self.frames) < MIN_DELAY => Some((image, start)),
+            Some((last, start)) => {
+                self.write(&last, start)?;
+                Some((image, self.frames))
+            }
+            None => Some((image, self.frames)),
+        };
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Writes the last frame, the file is incomplete without it
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some((last, start)) = self.pending.take() {
+            self.write(&last, start)?;
+        }
+        self.encoder.into_inner()?;
+        Ok(())
+    }
+
+    fn write(&mut self, image: &[u8], start: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let (width, height) = (self.size.0 * self.scale, self.size.1 * self.scale);
+        let mut frame = indexed_frame(width as u16, height as u16, image);
+        frame.delay = delay(start, self.frames).max(MIN_DELAY) as u16;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+// The screen only has a few colours, so they go straight into the frame's palette
+// Filters can blend more than a GIF palette holds, those frames get quantized instead
+fn indexed_frame(width: u16, height: u16, image: &[u8]) -> gif::Frame<'static> {
+    let mut palette = vec![];
+    let mut indices = HashMap::new();
+    let mut pixels = Vec::with_capacity(image.len() / 3);
+    for rgb in image.chunks_exact(3) {
+        let index = match indices.get(rgb) {
+            Some(&index) => index,
+            None if indices.len() == 256 => {
+                return gif::Frame::from_rgb_speed(width, height, image, QUANTIZE_SPEED);
+            }
+            None => {
+                let index = indices.len() as u8;
+                indices.insert(rgb, index);
+                palette.extend_from_slice(rgb);
+                index
+            }
+        };
+        pixels.push(index);
+    }
+    gif::Frame::from_palette_pixels(width, height, pixels, palette, None)
+}
+
+// Hundredths of a second between two frames, rounded from the start of the recording so
+// 60 Hz doesn't drift
+fn delay(start: u64, end: u64) -> u64 {
+    // GIF delays are in hundredths of a second
+    let rate = FRAME_RATE as u64;
+    let time = |frame: u64| (frame * 100 + rate / 2) / rate;
+    time(end) - time(start)
+}
+
+// Uncompressed YUV4MPEG2 video of every emulated frame, for lossless recordings
+// 4:4:4 so single pixel details keep their colour, ffmpeg and most players read it directly
+pub struct Y4mWriter {
+    file: BufWriter<File>,
+    size: (usize, usize),
+    scale: usize,
+}
+
+impl Y4mWriter {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        scale: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+            width * scale,
+            height * scale,
+            FRAME_RATE
+        )?;
+
+        Ok(Self {
+            file,
+            size: (width, height),
+            scale,
+        })
+    }
+
+    // Call once per emulated frame (60 Hz), whether the screen changed or not
+    pub fn add_frame(&mut self, frame: &Frame) -> Result<(), Box<dyn std::error::Error>> {
+        if (frame.width, frame.height) != self.size {
+            return Err("frame size changed while recording".into());
+        }
+
+        // Full planes of Y, then U, then V (BT.601, studio range)
+        let image = scaled_rgb(frame, self.scale);
+        let mut planes = [vec![], vec![], vec![]];
+        for pixel in image.chunks_exact(3) {
+            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            planes[0].push((16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8);
+            planes[1].push((128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8);
+            planes[2].push((128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8);
+        }
+
+        self.file.write_all(b"FRAME\n")?;
+        for plane in &planes {
+            self.file.write_all(plane)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_colours_are_kept_exactly() {
+        let image = [
+            0x10, 0x20, 0x30, 0xFF, 0xB0, 0x00, 0x10, 0x20, 0x30, 0x10, 0x20, 0x30,
+        ];
+        let frame = indexed_frame(2, 2, &image);
+
+        assert_eq!(frame.palette.as_deref(), Some(&image[..6]));
+        assert_eq!(&*frame.buffer, &[0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn too_many_colours_are_quantized() {
+        let image: Vec<u8> = (0..300u16)
+            .flat_map(|i| [i as u8, (i >> 8) as u8, 0])
+            .collect();
+        let frame = indexed_frame(300, 1, &image);
+
+        assert_eq!(frame.buffer.len(), 300);
+        assert!(frame.palette.unwrap().len() <= 256 * 3);
+    }
+}
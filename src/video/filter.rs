@@ -0,0 +1,227 @@
+use super::Rgb;
+
+// An RGB image, what the filters turn the CPU's framebuffer into
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>, // Row by row
+}
+
+impl Frame {
+    pub fn rows(&self) -> impl Iterator<Item = &[Rgb]> {
+        self.pixels.chunks_exact(self.width.max(1))
+    }
+
+    // Clamped to the edges, for filters looking at neighbours
+    fn pixel(&self, x: isize, y: isize) -> Rgb {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Ghosting(f32), // Unlit pixels fade out, keeping this much of their brightness each frame
+    Blend,         // Average of this frame and the last, so flickering sprites show at half
+    Scanlines(f32), // Doubles the size, every other row darkened by this much
+    Grid(f32),     // Triples the size, gaps between pixels darkened by this much (LCD grid)
+    Scale2x,       // Doubles the size smoothing diagonal edges (AdvMAME2x/EPX)
+}
+
+impl Filter {
+    // e.g. "ghosting", "ghosting:0.9", "scanlines:0.3", "epx"
+    pub fn from_name(spec: &str) -> Option<Self> {
+        let (name, amount) = match spec.split_once(':') {
+            Some((name, amount)) => (name, Some(amount.parse::<f32>().ok()?)),
+            None => (spec, None),
+        };
+        if amount.is_some_and(|amount| !(0.0..=1.0).contains(&amount)) {
+            return None;
+        }
+
+        match name {
+            "ghosting" => Some(Self::Ghosting(amount.unwrap_or(0.75))),
+            "blend" => Some(Self::Blend),
+            "scanlines" => Some(Self::Scanlines(amount.unwrap_or(0.5))),
+            "grid" => Some(Self::Grid(amount.unwrap_or(0.3))),
+            "scale2x" | "epx" => Some(Self::Scale2x),
+            _ => None,
+        }
+    }
+
+    // Fades work on pixel brightness before colouring, and need drawing every frame
+    fn is_temporal(&self) -> bool {
+        matches!(self, Self::Ghosting(_) | Self::Blend)
+    }
+}
+
+// Filters applied in order, except that fades always run first on the pixels' brightness
+// The rest work on the coloured frame
+#[derive(Default)]
+pub struct Pipeline {
+    filters: Vec<Filter>,
+    history: Vec<Vec<f32>>, // Brightness each fade filter remembers from the last frame
+}
+
+impl Pipeline {
+    pub fn new(filters: Vec<Filter>) -> Self {
+        Self {
+            history: vec![vec![]; filters.len()],
+            filters,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    // Fading pixels change without the screen changing, so every frame has to be drawn
+    pub fn is_animated(&self) -> bool {
+        self.filters.iter().any(Filter::is_temporal)
+    }
+
+    // `colors` gives the (background, foreground) of each pixel
+    pub fn render<I, J, F>(&mut self, pixels: I, width: usize, height: usize, colors: F) -> Frame
+    where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+        F: Fn(usize, usize) -> (Rgb, Rgb),
+    {
+        let mut levels = vec![0.0; width * height];
+        for (y, row) in pixels.into_iter().take(height).enumerate() {
+            for (x, pixel) in row.into_iter().take(width).enumerate() {
+                levels[y * width + x] = if pixel { 1.0 } else { 0.0 };
+            }
+        }
+
+        for (filter, history) in self.filters.iter().zip(&mut self.history) {
+            // Start over when the resolution changes
+            if history.len() != levels.len() {
+                *history = levels.clone();
+            }
+
+            match filter {
+                Filter::Ghosting(persistence) => {
+                    for (level, last) in levels.iter_mut().zip(history.iter_mut()) {
+                        *level = level.max(*last * persistence);
+                        *last = *level;
+                    }
+                }
+                Filter::Blend => {
+                    for (level, last) in levels.iter_mut().zip(history.iter_mut()) {
+                        (*level, *last) = ((*level + *last) / 2.0, *level);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let mut frame = Frame {
+            width,
+            height,
+            pixels: levels
+                .iter()
+                .enumerate()
+                .map(|(index, &level)| {
+                    let (background, foreground) = colors(index % width, index / width);
+                    mix(background, foreground, level)
+                })
+                .collect(),
+        };
+
+        for filter in &self.filters {
+            frame = match *filter {
+                Filter::Scanlines(darkness) => scanlines(&frame, darkness),
+                Filter::Grid(darkness) => grid(&frame, darkness),
+                Filter::Scale2x => scale2x(&frame),
+                Filter::Ghosting(_) | Filter::Blend => continue,
+            };
+        }
+        frame
+    }
+}
+
+fn mix(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount) as u8;
+    Rgb(
+        channel(from.0, to.0),
+        channel(from.1, to.1),
+        channel(from.2, to.2),
+    )
+}
+
+// Blows each pixel up to a scale x scale block, shaded by `shade(pixel, dx, dy)`
+fn upscale<F>(frame: &Frame, scale: usize, shade: F) -> Frame
+where
+    F: Fn(Rgb, usize, usize) -> Rgb,
+{
+    let width = frame.width * scale;
+    let pixels = (0..frame.height * scale)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let pixel = frame.pixels[(y / scale) * frame.width + x / scale];
+            shade(pixel, x % scale, y % scale)
+        })
+        .collect();
+
+    Frame {
+        width,
+        height: frame.height * scale,
+        pixels,
+    }
+}
+
+fn scanlines(frame: &Frame, darkness: f32) -> Frame {
+    upscale(frame, 2, |pixel, _, dy| match dy {
+        0 => pixel,
+        _ => mix(pixel, Rgb::BLACK, darkness),
+    })
+}
+
+fn grid(frame: &Frame, darkness: f32) -> Frame {
+    upscale(frame, 3, |pixel, dx, dy| match (dx, dy) {
+        (2, _) | (_, 2) => mix(pixel, Rgb::BLACK, darkness),
+        _ => pixel,
+    })
+}
+
+fn scale2x(frame: &Frame) -> Frame {
+    let width = frame.width * 2;
+    let mut pixels = vec![Rgb::BLACK; width * frame.height * 2];
+
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let (sx, sy) = (x as isize, y as isize);
+            let center = frame.pixel(sx, sy);
+            let above = frame.pixel(sx, sy - 1);
+            let left = frame.pixel(sx - 1, sy);
+            let right = frame.pixel(sx + 1, sy);
+            let below = frame.pixel(sx, sy + 1);
+
+            // Corners take a neighbour's colour where two neighbours meet along a diagonal
+            let (top_left, top_right, bottom_left, bottom_right) =
+                match above != below && left != right {
+                    true => (
+                        if left == above { left } else { center },
+                        if above == right { right } else { center },
+                        if left == below { left } else { center },
+                        if below == right { right } else { center },
+                    ),
+                    false => (center, center, center, center),
+                };
+
+            let index = y * 2 * width + x * 2;
+            pixels[index] = top_left;
+            pixels[index + 1] = top_right;
+            pixels[index + width] = bottom_left;
+            pixels[index + width + 1] = bottom_right;
+        }
+    }
+
+    Frame {
+        width,
+        height: frame.height * 2,
+        pixels,
+    }
+}
@@ -0,0 +1,86 @@
+use crate::cpu::ColorMap;
+
+use super::{
+    filter::{Frame, Pipeline},
+    palette::Palette,
+    Video, VP590_COLORS,
+};
+
+// Keeps the last frame in memory instead of showing it, for tests and headless runs
+#[derive(Default)]
+pub struct MemoryVideo {
+    frame: Option<Frame>,
+    draws: usize,
+    title: String,
+    palette: Palette,
+}
+
+impl MemoryVideo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last_frame(&self) -> Option<&Frame> {
+        self.frame.as_ref()
+    }
+
+    // Frames drawn so far, refreshes don't count
+    pub fn draw_count(&self) -> usize {
+        self.draws
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn store(&mut self, frame: Frame) {
+        self.frame = Some(frame);
+        self.draws += 1;
+    }
+}
+
+impl Video for MemoryVideo {
+    fn draw_to_window<I, J>(&mut self, pixels: I, width: usize, height: usize)
+    where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        let (background, foreground) = (self.palette.background(), self.palette.foreground());
+        let frame =
+            Pipeline::default().render(pixels, width, height, |_, _| (background, foreground));
+        self.store(frame);
+    }
+
+    fn draw_colored_to_window<I, J>(
+        &mut self,
+        pixels: I,
+        colors: &ColorMap,
+        width: usize,
+        height: usize,
+    ) where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        let background = VP590_COLORS[colors.background() as usize];
+        let frame = Pipeline::default().render(pixels, width, height, |x, y| {
+            (background, VP590_COLORS[colors.foreground(x, y) as usize])
+        });
+        self.store(frame);
+    }
+
+    fn draw_frame(&mut self, frame: &Frame) {
+        self.store(Frame {
+            width: frame.width,
+            height: frame.height,
+            pixels: frame.pixels.clone(),
+        });
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.title = String::from(title);
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = *palette;
+    }
+}
@@ -1,8 +1,122 @@
+pub mod capture;
+pub mod filter;
+pub mod memory_video;
+pub mod palette;
 pub mod sdl_video;
+pub mod terminal_video;
+
+use crate::cpu::ColorMap;
+
+use filter::Frame;
+use palette::Palette;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb(0, 0, 0);
+    pub const WHITE: Rgb = Rgb(0xFF, 0xFF, 0xFF);
+
+    // Parse a "#RRGGBB" (or "RRGGBB") string
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 {
+            return None;
+        }
+
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        Some(Self((value >> 16) as u8, (value >> 8) as u8, value as u8))
+    }
+
+    pub fn to_hex(&self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.0, self.1, self.2)
+    }
+}
+
+// VP-590 colour board (CHIP-8X), indexed by the colours in ColorMap
+pub const VP590_COLORS: [Rgb; 8] = [
+    Rgb(0x00, 0x00, 0x00), // Black
+    Rgb(0xFF, 0x00, 0x00), // Red
+    Rgb(0x00, 0x00, 0xFF), // Blue
+    Rgb(0xFF, 0x00, 0xFF), // Violet
+    Rgb(0x00, 0xFF, 0x00), // Green
+    Rgb(0xFF, 0xFF, 0x00), // Yellow
+    Rgb(0x00, 0xFF, 0xFF), // Aqua
+    Rgb(0xFF, 0xFF, 0xFF), // White
+];
+
+// How the frame is fitted into a window of a different size, leftover space is letterboxed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    Integer, // Largest whole multiple of the resolution, pixels stay square and even
+    Fit,     // As large as the window allows at the same aspect ratio
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "integer" => Some(Self::Integer),
+            "fit" => Some(Self::Fit),
+            _ => None,
+        }
+    }
+
+    // Where a width x height frame goes in the window: (x, y, width, height)
+    pub fn letterbox(
+        &self,
+        window: (u32, u32),
+        width: usize,
+        height: usize,
+    ) -> (i32, i32, u32, u32) {
+        let (width, height) = (width as u32, height as u32);
+        let (scaled_width, scaled_height) = match self {
+            Self::Integer => {
+                // Never scale below 1x, a tiny window just crops the frame
+                let scale = (window.0 / width).min(window.1 / height).max(1);
+                (width * scale, height * scale)
+            }
+            Self::Fit if window.0 * height <= window.1 * width => {
+                (window.0, window.0 * height / width)
+            }
+            Self::Fit => (window.1 * width / height, window.1),
+        };
+
+        (
+            (window.0 as i32 - scaled_width as i32) / 2,
+            (window.1 as i32 - scaled_height as i32) / 2,
+            scaled_width,
+            scaled_height,
+        )
+    }
+}
 
 pub trait Video {
-    fn draw_to_window<'a, I, J>(&mut self, pixels: I, width: usize, height: usize)
+    fn draw_to_window<I, J>(&mut self, pixels: I, width: usize, height: usize)
     where
         I: IntoIterator<Item = J>,
-        J: IntoIterator<Item = &'a bool>;
+        J: IntoIterator<Item = bool>;
+
+    // Backends without colour support just draw the pixels
+    fn draw_colored_to_window<I, J>(
+        &mut self,
+        pixels: I,
+        _colors: &ColorMap,
+        width: usize,
+        height: usize,
+    ) where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        self.draw_to_window(pixels, width, height);
+    }
+
+    // A frame that has already been coloured, e.g. by a filter::Pipeline
+    fn draw_frame(&mut self, frame: &Frame);
+
+    // Show the last frame again, after the window was resized or uncovered
+    fn refresh(&mut self) {}
+
+    fn set_title(&mut self, _title: &str) {}
+
+    fn set_palette(&mut self, _palette: &Palette) {}
 }
@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::database::hash_rom;
+
+use super::Rgb;
+
+// Background first, then the pixel colours for plane 1, plane 2 and both planes (XO-CHIP)
+// CHIP-8 and SCHIP only ever draw plane 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette(pub [Rgb; 4]);
+
+const PALETTES: [(&str, Palette); 5] = [
+    (
+        "vip",
+        Palette([
+            Rgb(0x00, 0x00, 0x00),
+            Rgb(0xFF, 0xFF, 0xFF),
+            Rgb(0xAA, 0xAA, 0xAA),
+            Rgb(0x55, 0x55, 0x55),
+        ]),
+    ),
+    // Dark pixels on the HP 48's green-grey LCD
+    (
+        "hp48",
+        Palette([
+            Rgb(0xA7, 0xB4, 0x8F),
+            Rgb(0x1F, 0x29, 0x1C),
+            Rgb(0x5E, 0x6B, 0x52),
+            Rgb(0x3B, 0x46, 0x35),
+        ]),
+    ),
+    (
+        "amber",
+        Palette([
+            Rgb(0x1A, 0x0F, 0x00),
+            Rgb(0xFF, 0xB0, 0x00),
+            Rgb(0xA0, 0x6A, 0x00),
+            Rgb(0xFF, 0xD8, 0x80),
+        ]),
+    ),
+    // Octo's default background, fill, fill2 and blend
+    (
+        "octo",
+        Palette([
+            Rgb(0x99, 0x66, 0x00),
+            Rgb(0xFF, 0xCC, 0x00),
+            Rgb(0xFF, 0x66, 0x00),
+            Rgb(0x66, 0x22, 0x00),
+        ]),
+    ),
+    // Pure colours with the most contrast against black and each other
+    (
+        "high-contrast",
+        Palette([
+            Rgb(0x00, 0x00, 0x00),
+            Rgb(0xFF, 0xFF, 0x00),
+            Rgb(0x00, 0xFF, 0xFF),
+            Rgb(0xFF, 0xFF, 0xFF),
+        ]),
+    ),
+];
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Self> {
+        PALETTES
+            .iter()
+            .find(|(palette_name, _)| *palette_name == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    // Name of the built-in palette with these colours, None for custom ones
+    pub fn name(&self) -> Option<&'static str> {
+        PALETTES
+            .iter()
+            .find(|(_, palette)| palette == self)
+            .map(|&(name, _)| name)
+    }
+
+    // Next built-in palette, for switching at runtime, custom palettes go back to the first
+    pub fn next(&self) -> Self {
+        let index = PALETTES.iter().position(|(_, palette)| palette == self);
+        match index {
+            Some(index) => PALETTES[(index + 1) % PALETTES.len()].1,
+            None => PALETTES[0].1,
+        }
+    }
+
+    // Background and foreground, optionally followed by the plane 2 and both planes colours
+    // Missing plane colours are mixed from the first two, more than four colours is an error
+    pub fn from_colors(colors: &[Rgb]) -> Option<Self> {
+        if colors.len() > 4 {
+            return None;
+        }
+
+        let (&background, &foreground) = (colors.first()?, colors.get(1)?);
+        let Rgb(r0, g0, b0) = background;
+        let Rgb(r1, g1, b1) = foreground;
+        let mixed = Rgb(
+            ((r0 as u16 + r1 as u16) / 2) as u8,
+            ((g0 as u16 + g1 as u16) / 2) as u8,
+            ((b0 as u16 + b1 as u16) / 2) as u8,
+        );
+
+        Some(Self([
+            background,
+            foreground,
+            colors.get(2).copied().unwrap_or(mixed),
+            colors.get(3).copied().unwrap_or(foreground),
+        ]))
+    }
+
+    // A built-in name or a list of colours, e.g. "#000000,#33FF33"
+    pub fn parse(arg: &str) -> Option<Self> {
+        Self::from_name(arg).or_else(|| {
+            let colors = arg
+                .split(',')
+                .map(Rgb::from_hex)
+                .collect::<Option<Vec<_>>>()?;
+            Self::from_colors(&colors)
+        })
+    }
+
+    pub fn background(&self) -> Rgb {
+        self.0[0]
+    }
+
+    pub fn foreground(&self) -> Rgb {
+        self.0[1]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        PALETTES[0].1
+    }
+}
+
+// Palettes picked at runtime, remembered per ROM (by SHA-1) in a JSON file
+// Built-in palettes are stored by name, custom ones as a list of colours
+pub struct PaletteStore {
+    path: PathBuf,
+    palettes: HashMap<String, String>,
+}
+
+impl PaletteStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            palettes: HashMap::new(),
+        }
+    }
+
+    // A missing file is an empty store, it gets created on the first save
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let palettes = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            palettes,
+        })
+    }
+
+    pub fn get(&self, rom: &[u8]) -> Option<Palette> {
+        Palette::parse(self.palettes.get(&hash_rom(rom))?)
+    }
+
+    pub fn set(&mut self, rom: &[u8], palette: Palette) {
+        let value = match palette.name() {
+            Some(name) => String::from(name),
+            None => palette
+                .0
+                .iter()
+                .map(Rgb::to_hex)
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        self.palettes.insert(hash_rom(rom), value);
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.palettes)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plane_colours() {
+        let palette = Palette::parse("#000000,#FFFFFF,#FF0000,#00FF00").unwrap();
+        assert_eq!(
+            palette.0,
+            [
+                Rgb(0x00, 0x00, 0x00),
+                Rgb(0xFF, 0xFF, 0xFF),
+                Rgb(0xFF, 0x00, 0x00),
+                Rgb(0x00, 0xFF, 0x00),
+            ]
+        );
+
+        // Missing plane colours are mixed
+        let palette = Palette::parse("#000000,#FFFFFF").unwrap();
+        assert_eq!(palette.0[2], Rgb(0x7F, 0x7F, 0x7F));
+        assert_eq!(palette.0[3], Rgb(0xFF, 0xFF, 0xFF));
+
+        assert_eq!(Palette::parse("#000000"), None);
+        assert_eq!(
+            Palette::parse("#000000,#FFFFFF,#FF0000,#00FF00,#0000FF"),
+            None
+        );
+    }
+
+    #[test]
+    fn stores_plane_colours() {
+        let palette = Palette::parse("#000000,#FFFFFF,#FF0000,#00FF00").unwrap();
+        let mut store = PaletteStore::new("palettes.json");
+        store.set(&[0x12, 0x00], palette);
+
+        assert_eq!(store.get(&[0x12, 0x00]), Some(palette));
+        assert_eq!(store.get(&[0x00, 0xE0]), None);
+    }
+}
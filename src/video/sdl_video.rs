@@ -1,10 +1,25 @@
-use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window, Sdl};
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
+    Sdl,
+};
 
-use super::Video;
+use crate::cpu::ColorMap;
+
+use super::{filter::Frame, palette::Palette, Rgb, Scaling, Video, VP590_COLORS};
+
+// Bytes per pixel in the RGB24 texture
+const PIXEL_SIZE: usize = 3;
 
 pub struct SDLVideo {
     canvas: Canvas<Window>,
-    scale: u32,
+    texture_creator: TextureCreator<WindowContext>,
+    texture: Option<Texture>, // Frame at native resolution, SDL scales it to the window
+    resolution: (usize, usize),
+    scaling: Scaling,
+    palette: Palette,
 }
 
 impl SDLVideo {
@@ -13,62 +28,145 @@ impl SDLVideo {
         scale: u32,
         width: usize,
         height: usize,
+        scaling: Scaling,
+        vsync: bool,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let video_subsystem = sdl_context.video()?;
 
+        // Initial size only, the window can be resized freely
         let window_width = width as u32 * scale;
         let window_height = height as u32 * scale;
 
         let window = video_subsystem
-            .window("TODO: BETTER NAME", window_width, window_height)
+            .window("CHIP-8 Emulator", window_width, window_height)
             .position_centered()
+            .resizable()
             .build()?;
 
-        let canvas = window.into_canvas().build()?;
+        // With vsync, presenting waits for the display so frames aren't torn
+        let canvas = match vsync {
+            true => window.into_canvas().present_vsync().build()?,
+            false => window.into_canvas().build()?,
+        };
+        let texture_creator = canvas.texture_creator();
 
-        Ok(Self { canvas, scale })
+        Ok(Self {
+            canvas,
+            texture_creator,
+            texture: None,
+            resolution: (width, height),
+            scaling,
+            palette: Palette::default(),
+        })
     }
-}
 
-impl Video for SDLVideo {
-    fn draw_to_window<'a, I, J>(&mut self, pixels: I, width: usize, height: usize)
+    // Writes the frame into the texture, recreating it if the resolution changed
+    fn upload<I, J, T, F>(&mut self, pixels: I, width: usize, height: usize, color: F)
     where
         I: IntoIterator<Item = J>,
-        J: IntoIterator<Item = &'a bool>,
+        J: IntoIterator<Item = T>,
+        F: Fn(T, usize, usize) -> Rgb,
     {
-        self.canvas.set_draw_color(Color::BLACK);
-        self.canvas.clear();
+        if self.texture.is_none() || self.resolution != (width, height) {
+            if let Some(texture) = self.texture.take() {
+                // Safe, the canvas it was created from is still alive
+                unsafe { texture.destroy() };
+            }
+            self.texture = Some(
+                self.texture_creator
+                    .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+                    .unwrap(),
+            );
+            self.resolution = (width, height);
+        }
 
-        let scale = self.scale;
-
-        let rects = pixels
-            .into_iter()
-            .take(height)
-            .enumerate()
-            .flat_map(|(y, row)| {
-                row.into_iter()
-                    .take(width)
-                    .enumerate()
-                    .filter_map(move |(x, pixel)| {
-                        let rect = Rect::new(
-                            x as i32 * scale as i32,
-                            y as i32 * scale as i32,
-                            scale,
-                            scale,
-                        );
-                        match pixel {
-                            true => Some(rect),
-                            false => None,
-                        }
-                    })
+        let texture = self.texture.as_mut().unwrap();
+        texture
+            .with_lock(None, |buffer, pitch| {
+                for (y, row) in pixels.into_iter().take(height).enumerate() {
+                    let line = &mut buffer[y * pitch..][..width * PIXEL_SIZE];
+                    for ((x, pixel), out) in row
+                        .into_iter()
+                        .take(width)
+                        .enumerate()
+                        .zip(line.chunks_exact_mut(PIXEL_SIZE))
+                    {
+                        let Rgb(r, g, b) = color(pixel, x, y);
+                        out.copy_from_slice(&[r, g, b]);
+                    }
+                }
             })
-            .collect::<Vec<Rect>>();
+            .unwrap();
+    }
+
+    // Draws the texture letterboxed into the window
+    fn present(&mut self) {
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
 
-        // Draw pixels
-        self.canvas.set_draw_color(Color::WHITE);
-        self.canvas.fill_rects(rects.as_slice()).unwrap();
+        if let Some(texture) = &self.texture {
+            let (width, height) = self.resolution;
+            let window = self.canvas.output_size().unwrap();
+            let (x, y, w, h) = self.scaling.letterbox(window, width, height);
+            self.canvas
+                .copy(texture, None, Rect::new(x, y, w, h))
+                .unwrap();
+        }
 
         // Update screen
         self.canvas.present();
     }
 }
+
+impl Video for SDLVideo {
+    fn draw_to_window<I, J>(&mut self, pixels: I, width: usize, height: usize)
+    where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        let (background, foreground) = (self.palette.background(), self.palette.foreground());
+        self.upload(pixels, width, height, |pixel, _, _| match pixel {
+            true => foreground,
+            false => background,
+        });
+        self.present();
+    }
+
+    fn draw_colored_to_window<I, J>(
+        &mut self,
+        pixels: I,
+        colors: &ColorMap,
+        width: usize,
+        height: usize,
+    ) where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        let background = VP590_COLORS[colors.background() as usize];
+        self.upload(pixels, width, height, |pixel, x, y| match pixel {
+            true => VP590_COLORS[colors.foreground(x, y) as usize],
+            false => background,
+        });
+        self.present();
+    }
+
+    fn draw_frame(&mut self, frame: &Frame) {
+        self.upload(frame.rows(), frame.width, frame.height, |&pixel, _, _| {
+            pixel
+        });
+        self.present();
+    }
+
+    fn refresh(&mut self) {
+        self.present();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        // Titles with interior NULs are rejected by SDL, ignore those
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = *palette;
+    }
+}
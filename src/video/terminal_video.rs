@@ -0,0 +1,220 @@
+use std::io::{stdout, Stdout, Write};
+
+use crossterm::{
+    cursor, execute, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal,
+};
+
+use crate::cpu::ColorMap;
+
+use super::{
+    filter::{Frame, Pipeline},
+    palette::Palette,
+    Rgb, Video, VP590_COLORS,
+};
+
+// Characters the screen is drawn with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    HalfBlock, // Upper half block in two colours, each cell is 1x2 pixels
+    Braille,   // Braille dots in one colour, each cell is 2x4 pixels
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    glyph: char,
+    foreground: Rgb,
+    background: Rgb,
+}
+
+// Draws to the terminal with ANSI colours, only rewriting the cells that changed
+// The terminal has to fit the screen, hires half blocks take 128x32 cells
+pub struct TerminalVideo {
+    out: Stdout,
+    glyphs: Glyphs,
+    palette: Palette,
+    cells: Vec<Cell>, // What the terminal shows now
+    columns: usize,
+}
+
+impl TerminalVideo {
+    // Takes over the terminal's alternate screen until dropped
+    pub fn new(glyphs: Glyphs) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut out = stdout();
+        execute!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+
+        Ok(Self {
+            out,
+            glyphs,
+            palette: Palette::default(),
+            cells: vec![],
+            columns: 0,
+        })
+    }
+
+    // Pixels that aren't `background` are lit, for glyphs that can only show one colour
+    fn cells(&self, frame: &Frame, background: Rgb) -> (Vec<Cell>, usize) {
+        let pixel = |x: usize, y: usize| match x < frame.width && y < frame.height {
+            true => frame.pixels[y * frame.width + x],
+            false => background,
+        };
+
+        match self.glyphs {
+            Glyphs::HalfBlock => {
+                let cells = (0..frame.height.div_ceil(2))
+                    .flat_map(|row| (0..frame.width).map(move |x| (x, row * 2)))
+                    .map(|(x, y)| Cell {
+                        glyph: '▀',
+                        foreground: pixel(x, y),
+                        background: pixel(x, y + 1),
+                    })
+                    .collect();
+                (cells, frame.width)
+            }
+            Glyphs::Braille => {
+                // Dot bits of each pixel in a 2x4 cell, see U+2800
+                const DOTS: [[u32; 2]; 4] =
+                    [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+                let columns = frame.width.div_ceil(2);
+                let cells = (0..frame.height.div_ceil(4))
+                    .flat_map(|row| (0..columns).map(move |column| (column * 2, row * 4)))
+                    .map(|(x, y)| {
+                        let mut dots = 0;
+                        let mut foreground = background;
+                        for (dy, row) in DOTS.iter().enumerate() {
+                            for (dx, dot) in row.iter().enumerate() {
+                                let color = pixel(x + dx, y + dy);
+                                if color != background {
+                                    dots |= dot;
+                                    foreground = color;
+                                }
+                            }
+                        }
+
+                        Cell {
+                            glyph: char::from_u32(0x2800 + dots).unwrap_or(' '),
+                            foreground,
+                            background,
+                        }
+                    })
+                    .collect();
+                (cells, columns)
+            }
+        }
+    }
+
+    fn show(&mut self, cells: Vec<Cell>, columns: usize) -> std::io::Result<()> {
+        // Anything else on the screen has to go when the layout changes
+        let full = columns != self.columns || cells.len() != self.cells.len();
+        if full {
+            queue!(
+                self.out,
+                ResetColor,
+                terminal::Clear(terminal::ClearType::All)
+            )?;
+        }
+
+        // Skip cursor moves and colour changes the terminal doesn't need
+        let mut position = None;
+        let mut colors = None;
+        for (index, &cell) in cells.iter().enumerate() {
+            if !full && self.cells[index] == cell {
+                continue;
+            }
+
+            let (x, y) = (index % columns, index / columns);
+            if position != Some((x, y)) {
+                queue!(self.out, cursor::MoveTo(x as u16, y as u16))?;
+            }
+            if colors != Some((cell.foreground, cell.background)) {
+                queue!(
+                    self.out,
+                    SetForegroundColor(to_color(cell.foreground)),
+                    SetBackgroundColor(to_color(cell.background))
+                )?;
+                colors = Some((cell.foreground, cell.background));
+            }
+            queue!(self.out, Print(cell.glyph))?;
+            position = Some((x + 1, y));
+        }
+        self.out.flush()?;
+
+        self.cells = cells;
+        self.columns = columns;
+        Ok(())
+    }
+}
+
+fn to_color(Rgb(r, g, b): Rgb) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+impl Video for TerminalVideo {
+    fn draw_to_window<I, J>(&mut self, pixels: I, width: usize, height: usize)
+    where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        let (background, foreground) = (self.palette.background(), self.palette.foreground());
+        let frame =
+            Pipeline::default().render(pixels, width, height, |_, _| (background, foreground));
+        let (cells, columns) = self.cells(&frame, background);
+        // Nothing sensible to do if the terminal has gone away
+        let _ = self.show(cells, columns);
+    }
+
+    fn draw_colored_to_window<I, J>(
+        &mut self,
+        pixels: I,
+        colors: &ColorMap,
+        width: usize,
+        height: usize,
+    ) where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = bool>,
+    {
+        let background = VP590_COLORS[colors.background() as usize];
+        let frame = Pipeline::default().render(pixels, width, height, |x, y| {
+            (background, VP590_COLORS[colors.foreground(x, y) as usize])
+        });
+        let (cells, columns) = self.cells(&frame, background);
+        let _ = self.show(cells, columns);
+    }
+
+    fn draw_frame(&mut self, frame: &Frame) {
+        let (cells, columns) = self.cells(frame, self.palette.background());
+        let _ = self.show(cells, columns);
+    }
+
+    fn refresh(&mut self) {
+        // Everything is drawn again, the terminal may have lost some of it
+        let cells = std::mem::take(&mut self.cells);
+        let _ = self.show(cells, self.columns);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        let _ = execute!(self.out, terminal::SetTitle(title));
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = *palette;
+    }
+}
+
+impl Drop for TerminalVideo {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.out,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+    }
+}
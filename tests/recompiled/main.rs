@@ -0,0 +1,82 @@
+// Runs a recompiled ROM next to the interpreter, they must end up in the same state
+// rom.rs is generated, rebuild it with `REGENERATE=1 cargo test --test recompiled`
+use chip8_emulator_rs::cpu::{CPUVariant, OutOfBounds, QuirkOverrides, CPU};
+
+#[rustfmt::skip]
+mod rom;
+
+// Instructions per frame
+const TICKRATE: usize = 30;
+const FRAMES: usize = 50;
+
+#[rustfmt::skip]
+const ROM: &[u8] = &[
+    0x6A, 0x3C, // 200: VA = 60
+    0x6B, 0x1E, // 202: VB = 30
+    0x60, 0x01, // 204: V0 = 1
+    0x70, 0x03, // 206: V0 += 3
+    0x81, 0x06, // 208: V1 >>= 1
+    0x82, 0x01, // 20A: V2 |= V0
+    0x83, 0x14, // 20C: V3 += V1
+    0xA3, 0x00, // 20E: I = 300
+    0xF2, 0x1E, // 210: I += V2
+    0xF3, 0x55, // 212: store V0-V3
+    0xF3, 0x65, // 214: load V0-V3
+    0xF0, 0x29, // 216: I = digit V0
+    0xDA, 0xB5, // 218: draw at VA, VB, wrapping off the corner
+    0x7A, 0x05, // 21A: VA += 5
+    0x12, 0x06, // 21C: jump 206
+];
+
+// Everything differs from plain CHIP-8, so a quirk the module forgets shows up
+fn quirks() -> QuirkOverrides {
+    QuirkOverrides {
+        logic_quirk: Some(false),
+        shift_quirk: Some(true),
+        vblank_quirk: Some(false),
+        wrap_quirk: Some(true),
+        i_overflow_quirk: Some(true),
+        load_store_offset: Some(None),
+        out_of_bounds: Some(OutOfBounds::Clamp),
+        ..Default::default()
+    }
+}
+
+fn interpreted() -> CPU {
+    let mut cpu = CPU::new(CPUVariant::Chip8);
+    cpu.apply_quirks(&quirks());
+    cpu.load_rom_bytes(ROM);
+    cpu
+}
+
+fn state(cpu: &CPU) -> ([u8; 0x10], usize, usize, Vec<Vec<u64>>) {
+    let display = cpu.display();
+    let rows = (0..display.height()).map(|y| display.row(y).to_vec());
+    (*cpu.registers(), cpu.index(), cpu.pc(), rows.collect())
+}
+
+#[test]
+fn module_is_up_to_date() {
+    let source = interpreted().recompile(CPUVariant::Chip8, ROM);
+    if std::env::var_os("REGENERATE").is_some() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/recompiled/rom.rs");
+        std::fs::write(path, &source).unwrap();
+    }
+    assert_eq!(source, include_str!("rom.rs"));
+}
+
+#[test]
+fn recompiled_matches_interpreter() {
+    let mut interpreter = interpreted();
+    let mut recompiled = rom::cpu();
+
+    for _ in 0..FRAMES {
+        interpreter.run(TICKRATE);
+        rom::run(&mut recompiled, TICKRATE);
+        for cpu in [&mut interpreter, &mut recompiled] {
+            cpu.decrement_timers();
+            cpu.reset_vblank();
+        }
+        assert_eq!(state(&interpreter), state(&recompiled));
+    }
+}
@@ -0,0 +1,113 @@
+// Generated by chip8-emulator-rs recompile, do not edit
+#![allow(non_snake_case, unused_variables)]
+
+use chip8_emulator_rs::cpu::{CPUVariant, OutOfBounds, QuirkOverrides, RecompiledBlock, CPU};
+
+pub const ROM: &[u8] = &[0x6A, 0x3C, 0x6B, 0x1E, 0x60, 0x01, 0x70, 0x03, 0x81, 0x06, 0x82, 0x01, 0x83, 0x14, 0xA3, 0x00, 0xF2, 0x1E, 0xF3, 0x55, 0xF3, 0x65, 0xF0, 0x29, 0xDA, 0xB5, 0x7A, 0x05, 0x12, 0x06];
+
+pub fn cpu() -> CPU {
+    let mut cpu = CPU::new(CPUVariant::Chip8);
+    cpu.apply_quirks(&QuirkOverrides {
+        logic_quirk: Some(false),
+        shift_quirk: Some(true),
+        jump_quirk: Some(false),
+        vblank_quirk: Some(false),
+        wrap_quirk: Some(true),
+        i_overflow_quirk: Some(true),
+        key_wait_on_press: Some(false),
+        flag_first_quirk: Some(false),
+        min_sound_timer: Some(2),
+        load_store_offset: Some(None),
+        memory_size: Some(4096),
+        out_of_bounds: Some(OutOfBounds::Clamp),
+        read_only_interpreter: Some(false),
+    });
+    cpu.load_rom_bytes(ROM);
+    cpu
+}
+
+pub fn run(cpu: &mut CPU, cycles: usize) {
+    cpu.run_recompiled(cycles, block);
+}
+
+pub fn block(pc: usize) -> Option<&'static RecompiledBlock> {
+    match pc {
+        0x200 => Some(&BLOCK_200),
+        0x206 => Some(&BLOCK_206),
+        0x21A => Some(&BLOCK_21A),
+        _ => None,
+    }
+}
+
+static BLOCK_200: RecompiledBlock = RecompiledBlock {
+    start: 0x200,
+    code: &[0x6A, 0x3C, 0x6B, 0x1E, 0x60, 0x01, 0x70, 0x03, 0x81, 0x06, 0x82, 0x01, 0x83, 0x14, 0xA3, 0x00, 0xF2, 0x1E],
+    len: 9,
+    last_opcode: 0xF21E,
+    run: block_200,
+};
+
+fn block_200(V: &mut [u8; 0x10], I: &mut usize) -> usize {
+    // 0x200: 6A3C  vA := 0x3C
+    V[0xA] = 0x3C;
+    // 0x202: 6B1E  vB := 0x1E
+    V[0xB] = 0x1E;
+    // 0x204: 6001  v0 := 0x01
+    V[0x0] = 0x01;
+    // 0x206: 7003  v0 += 0x03
+    V[0x0] = V[0x0].wrapping_add(0x03);
+    // 0x208: 8106  v1 >>= v0
+    { let (result, flag) = (V[0x1] >> 1, V[0x1] & 0x1); V[0x1] = result; V[0xF] = flag; }
+    // 0x20A: 8201  v2 |= v0
+    V[0x2] |= V[0x0];
+    // 0x20C: 8314  v3 += v1
+    { let (result, flag) = (V[0x3].wrapping_add(V[0x1]), V[0x3].checked_add(V[0x1]).is_none() as u8); V[0x3] = result; V[0xF] = flag; }
+    // 0x20E: A300  i := 0x300
+    *I = 0x300;
+    // 0x210: F21E  i += v2
+    let sum = *I + V[0x2] as usize;
+    V[0xF] = (sum > 0xFFF) as u8;
+    *I = sum & 0xFFF;
+    0x212
+}
+
+static BLOCK_206: RecompiledBlock = RecompiledBlock {
+    start: 0x206,
+    code: &[0x70, 0x03, 0x81, 0x06, 0x82, 0x01, 0x83, 0x14, 0xA3, 0x00, 0xF2, 0x1E],
+    len: 6,
+    last_opcode: 0xF21E,
+    run: block_206,
+};
+
+fn block_206(V: &mut [u8; 0x10], I: &mut usize) -> usize {
+    // 0x206: 7003  v0 += 0x03
+    V[0x0] = V[0x0].wrapping_add(0x03);
+    // 0x208: 8106  v1 >>= v0
+    { let (result, flag) = (V[0x1] >> 1, V[0x1] & 0x1); V[0x1] = result; V[0xF] = flag; }
+    // 0x20A: 8201  v2 |= v0
+    V[0x2] |= V[0x0];
+    // 0x20C: 8314  v3 += v1
+    { let (result, flag) = (V[0x3].wrapping_add(V[0x1]), V[0x3].checked_add(V[0x1]).is_none() as u8); V[0x3] = result; V[0xF] = flag; }
+    // 0x20E: A300  i := 0x300
+    *I = 0x300;
+    // 0x210: F21E  i += v2
+    let sum = *I + V[0x2] as usize;
+    V[0xF] = (sum > 0xFFF) as u8;
+    *I = sum & 0xFFF;
+    0x212
+}
+
+static BLOCK_21A: RecompiledBlock = RecompiledBlock {
+    start: 0x21A,
+    code: &[0x7A, 0x05, 0x12, 0x06],
+    len: 2,
+    last_opcode: 0x1206,
+    run: block_21a,
+};
+
+fn block_21a(V: &mut [u8; 0x10], I: &mut usize) -> usize {
+    // 0x21A: 7A05  vA += 0x05
+    V[0xA] = V[0xA].wrapping_add(0x05);
+    // 0x21C: 1206  jump 0x206
+    0x206
+}